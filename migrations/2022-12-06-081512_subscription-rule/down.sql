@@ -0,0 +1 @@
+ALTER TABLE subscriptions DROP COLUMN rule;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE subscriptions ADD COLUMN rule TEXT;
\ No newline at end of file
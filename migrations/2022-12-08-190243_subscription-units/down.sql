@@ -0,0 +1,2 @@
+ALTER TABLE subscriptions DROP COLUMN speed_unit;
+ALTER TABLE subscriptions DROP COLUMN threshold;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE subscriptions ADD COLUMN threshold REAL;
+ALTER TABLE subscriptions ADD COLUMN speed_unit TEXT;
\ No newline at end of file
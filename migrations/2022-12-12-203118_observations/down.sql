@@ -0,0 +1,3 @@
+ALTER TABLE subscriptions DROP COLUMN digest_at;
+DROP INDEX observations_source_time;
+DROP TABLE observations;
\ No newline at end of file
@@ -0,0 +1,11 @@
+CREATE TABLE observations (
+  id INTEGER PRIMARY KEY NOT NULL,
+  source TEXT NOT NULL,
+  time INTEGER NOT NULL,
+  utc_offset INTEGER NOT NULL,
+  direction INTEGER NOT NULL,
+  avg_speed REAL NOT NULL,
+  gust_speed REAL
+);
+CREATE UNIQUE INDEX observations_source_time ON observations(source, time);
+ALTER TABLE subscriptions ADD COLUMN digest_at TEXT;
\ No newline at end of file
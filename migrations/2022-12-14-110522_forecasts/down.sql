@@ -0,0 +1,3 @@
+ALTER TABLE subscriptions DROP COLUMN forecast_at;
+DROP INDEX forecasts_source_time;
+DROP TABLE forecasts;
\ No newline at end of file
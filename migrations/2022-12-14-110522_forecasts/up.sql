@@ -0,0 +1,12 @@
+CREATE TABLE forecasts (
+  id INTEGER PRIMARY KEY NOT NULL,
+  source TEXT NOT NULL,
+  time INTEGER NOT NULL,
+  utc_offset INTEGER NOT NULL,
+  fetched_at INTEGER NOT NULL,
+  direction INTEGER NOT NULL,
+  avg_speed REAL NOT NULL,
+  gust_speed REAL
+);
+CREATE UNIQUE INDEX forecasts_source_time ON forecasts(source, time);
+ALTER TABLE subscriptions ADD COLUMN forecast_at TEXT;
\ No newline at end of file
@@ -0,0 +1,28 @@
+CREATE TABLE observations_old (
+  id INTEGER PRIMARY KEY NOT NULL,
+  source TEXT NOT NULL,
+  time INTEGER NOT NULL,
+  utc_offset INTEGER NOT NULL,
+  direction INTEGER NOT NULL,
+  avg_speed REAL NOT NULL,
+  gust_speed REAL
+);
+INSERT INTO observations_old SELECT * FROM observations WHERE direction IS NOT NULL;
+DROP TABLE observations;
+ALTER TABLE observations_old RENAME TO observations;
+CREATE UNIQUE INDEX observations_source_time ON observations(source, time);
+
+CREATE TABLE forecasts_old (
+  id INTEGER PRIMARY KEY NOT NULL,
+  source TEXT NOT NULL,
+  time INTEGER NOT NULL,
+  utc_offset INTEGER NOT NULL,
+  fetched_at INTEGER NOT NULL,
+  direction INTEGER NOT NULL,
+  avg_speed REAL NOT NULL,
+  gust_speed REAL
+);
+INSERT INTO forecasts_old SELECT * FROM forecasts WHERE direction IS NOT NULL;
+DROP TABLE forecasts;
+ALTER TABLE forecasts_old RENAME TO forecasts;
+CREATE UNIQUE INDEX forecasts_source_time ON forecasts(source, time);
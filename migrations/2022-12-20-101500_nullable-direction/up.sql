@@ -0,0 +1,29 @@
+-- SQLite can't drop NOT NULL constraint, so tables are recreated
+CREATE TABLE observations_new (
+  id INTEGER PRIMARY KEY NOT NULL,
+  source TEXT NOT NULL,
+  time INTEGER NOT NULL,
+  utc_offset INTEGER NOT NULL,
+  direction INTEGER,
+  avg_speed REAL NOT NULL,
+  gust_speed REAL
+);
+INSERT INTO observations_new SELECT * FROM observations;
+DROP TABLE observations;
+ALTER TABLE observations_new RENAME TO observations;
+CREATE UNIQUE INDEX observations_source_time ON observations(source, time);
+
+CREATE TABLE forecasts_new (
+  id INTEGER PRIMARY KEY NOT NULL,
+  source TEXT NOT NULL,
+  time INTEGER NOT NULL,
+  utc_offset INTEGER NOT NULL,
+  fetched_at INTEGER NOT NULL,
+  direction INTEGER,
+  avg_speed REAL NOT NULL,
+  gust_speed REAL
+);
+INSERT INTO forecasts_new SELECT * FROM forecasts;
+DROP TABLE forecasts;
+ALTER TABLE forecasts_new RENAME TO forecasts;
+CREATE UNIQUE INDEX forecasts_source_time ON forecasts(source, time);
@@ -0,0 +1,2 @@
+DROP INDEX sessions_source_started_at;
+DROP TABLE sessions;
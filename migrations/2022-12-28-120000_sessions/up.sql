@@ -0,0 +1,12 @@
+CREATE TABLE sessions (
+  id INTEGER PRIMARY KEY NOT NULL,
+  source TEXT NOT NULL,
+  started_at INTEGER NOT NULL,
+  ended_at INTEGER NOT NULL,
+  utc_offset INTEGER NOT NULL,
+  peak_speed REAL NOT NULL,
+  avg_speed REAL NOT NULL,
+  direction INTEGER,
+  observations INTEGER NOT NULL
+);
+CREATE UNIQUE INDEX sessions_source_started_at ON sessions(source, started_at);
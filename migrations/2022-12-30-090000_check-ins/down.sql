@@ -0,0 +1,4 @@
+DROP INDEX alerts_session;
+DROP TABLE alerts;
+DROP INDEX check_ins_session_user;
+DROP TABLE check_ins;
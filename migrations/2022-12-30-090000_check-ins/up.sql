@@ -0,0 +1,18 @@
+CREATE TABLE check_ins (
+  id INTEGER PRIMARY KEY NOT NULL,
+  session_id INTEGER NOT NULL REFERENCES sessions(id),
+  user_id INTEGER NOT NULL,
+  name TEXT NOT NULL,
+  status TEXT NOT NULL,
+  updated_at INTEGER NOT NULL
+);
+CREATE UNIQUE INDEX check_ins_session_user ON check_ins(session_id, user_id);
+
+CREATE TABLE alerts (
+  id INTEGER PRIMARY KEY NOT NULL,
+  session_id INTEGER NOT NULL REFERENCES sessions(id),
+  chat_id INTEGER NOT NULL,
+  message_id INTEGER NOT NULL,
+  text TEXT NOT NULL
+);
+CREATE INDEX alerts_session ON alerts(session_id);
@@ -0,0 +1,6 @@
+DELETE FROM forecasts WHERE EXISTS (
+  SELECT 1 FROM forecasts AS newer
+  WHERE newer.source = forecasts.source AND newer.time = forecasts.time AND newer.fetched_at > forecasts.fetched_at
+);
+DROP INDEX forecasts_source_time_fetched_at;
+CREATE UNIQUE INDEX forecasts_source_time ON forecasts(source, time);
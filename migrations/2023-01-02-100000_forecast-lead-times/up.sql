@@ -0,0 +1,3 @@
+-- every fetched forecast is kept, so forecasts can be compared by lead time
+DROP INDEX forecasts_source_time;
+CREATE UNIQUE INDEX forecasts_source_time_fetched_at ON forecasts(source, time, fetched_at);
@@ -0,0 +1,233 @@
+//! Forecast accuracy
+//!
+//! Each forecasted hour is compared against observations made within half an hour of it. Observations
+//! are averaged (speed arithmetically, direction as a circular mean) before comparison.
+//!
+//! Hours are compared as they were forecasted [`HORIZON_HOURS`] in advance, the way good wind notices see them
+//! the day before. Later forecasts are more accurate and would make the report look better than the notices are.
+use crate::{parser::Observation, units::SpeedUnit};
+use chrono::Duration;
+use std::fmt::Write;
+
+/// How long in advance forecasts are compared
+pub const HORIZON_HOURS: i64 = 24;
+
+/// Observations closer than this to the forecasted hour are compared with it
+const MATCH_WINDOW_MINUTES: i64 = 30;
+
+#[derive(Debug, PartialEq)]
+pub struct Accuracy {
+    /// Number of forecasted hours with observations
+    pub samples: usize,
+    /// Mean difference between forecasted and observed speed (m/s). Positive if forecast overestimates wind
+    pub speed_bias: f32,
+    /// Mean absolute difference between forecasted and observed speed (m/s)
+    pub speed_mae: f32,
+    /// Mean absolute angle (deg.) between forecasted and observed direction.
+    /// `None` if wind was calm or variable all the time
+    pub direction_mae: Option<f32>,
+    /// Share of hours above threshold which were forecasted to be above threshold.
+    /// `None` if wind was never above threshold
+    pub hit_rate: Option<f32>,
+    /// Share of hours forecasted to be above threshold which were not.
+    /// `None` if wind was never forecasted to be above threshold
+    pub false_alarm_rate: Option<f32>,
+}
+
+impl Accuracy {
+    /// Compares forecast with observations. Both should be ordered by time.
+    ///
+    /// Returns `None` if there are no forecasted hours with observations.
+    pub fn new(
+        forecast: &[Observation],
+        observations: &[Observation],
+        threshold: f32,
+    ) -> Option<Self> {
+        let window = Duration::minutes(MATCH_WINDOW_MINUTES);
+        let mut pairs = vec![];
+        for hour in forecast {
+            let observed = observations
+                .iter()
+                .skip_while(|o| o.time < hour.time - window)
+                .take_while(|o| o.time < hour.time + window)
+                .collect::<Vec<_>>();
+            if observed.is_empty() {
+                continue;
+            }
+            let speed = observed.iter().map(|o| o.avg_speed).sum::<f32>() / observed.len() as f32;
+            let direction = mean_direction(observed.iter().filter_map(|o| o.direction));
+            pairs.push((hour, speed, direction));
+        }
+        if pairs.is_empty() {
+            return None;
+        }
+
+        let n = pairs.len() as f32;
+        let speed_bias = pairs.iter().map(|(f, s, _)| f.avg_speed - s).sum::<f32>() / n;
+        let speed_mae = pairs
+            .iter()
+            .map(|(f, s, _)| (f.avg_speed - s).abs())
+            .sum::<f32>()
+            / n;
+        // hours with calm or variable wind have no direction to compare
+        let direction_errors = pairs
+            .iter()
+            .filter_map(|(f, _, d)| Some(angle_between(f.direction? as f32, (*d)?)))
+            .collect::<Vec<_>>();
+        let direction_mae = (!direction_errors.is_empty())
+            .then(|| direction_errors.iter().sum::<f32>() / direction_errors.len() as f32);
+
+        // (forecasted above threshold, observed above threshold) for each hour
+        let above = pairs
+            .iter()
+            .map(|(f, s, _)| (f.avg_speed >= threshold, *s >= threshold))
+            .collect::<Vec<_>>();
+        let count =
+            |predicate: fn(&(bool, bool)) -> bool| above.iter().filter(|a| predicate(a)).count();
+        let ratio = |a: usize, b: usize| (b > 0).then(|| a as f32 / b as f32);
+        let hits = count(|(f, o)| *f && *o);
+        let hit_rate = ratio(hits, count(|(_, o)| *o));
+        let false_alarm_rate = ratio(count(|(f, o)| *f && !*o), count(|(f, _)| *f));
+
+        Some(Self {
+            samples: pairs.len(),
+            speed_bias,
+            speed_mae,
+            direction_mae,
+            hit_rate,
+            false_alarm_rate,
+        })
+    }
+
+    pub fn format(&self, spot: &str, days: i64, unit: SpeedUnit) -> String {
+        let mut result = format!(
+            "Forecast accuracy for {} ({} days, {} hours, {}h ahead)\n",
+            spot, days, self.samples, HORIZON_HOURS
+        );
+        // Beaufort scale is not linear, so speed differences are shown in m/s
+        let speed = |value: f32| match unit {
+            SpeedUnit::Beaufort => SpeedUnit::MetersPerSecond.format(value),
+            unit => unit.format(value),
+        };
+        let sign = if self.speed_bias >= 0. { "+" } else { "-" };
+        writeln!(
+            result,
+            "Speed bias {}{}",
+            sign,
+            speed(self.speed_bias.abs())
+        )
+        .unwrap();
+        writeln!(result, "Speed MAE {}", speed(self.speed_mae)).unwrap();
+        match self.direction_mae {
+            Some(mae) => writeln!(result, "Direction MAE {:.0}°", mae),
+            None => writeln!(result, "Direction MAE n/a"),
+        }
+        .unwrap();
+        let percent = |value: Option<f32>| match value {
+            Some(value) => format!("{:.0}%", value * 100.),
+            None => "n/a".to_string(),
+        };
+        write!(
+            result,
+            "Good wind hit rate {}, false alarms {}",
+            percent(self.hit_rate),
+            percent(self.false_alarm_rate)
+        )
+        .unwrap();
+        result
+    }
+}
+
+/// Circular mean of directions (deg.). `None` for an empty input
+pub(crate) fn mean_direction(directions: impl IntoIterator<Item = u16>) -> Option<f32> {
+    let (sin, cos, n) = directions
+        .into_iter()
+        .map(|d| (d as f32).to_radians())
+        .fold((0., 0., 0), |(sin, cos, n), a: f32| {
+            (sin + a.sin(), cos + a.cos(), n + 1)
+        });
+    (n > 0).then(|| sin.atan2(cos).to_degrees().rem_euclid(360.))
+}
+
+/// Smallest angle (deg.) between two directions
+fn angle_between(a: f32, b: f32) -> f32 {
+    let diff = (a - b).rem_euclid(360.);
+    diff.min(360. - diff)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use chrono::DateTime;
+
+    fn observation(time: &str, avg_speed: f32, direction: u16) -> Observation {
+        Observation {
+            time: DateTime::parse_from_rfc3339(&format!("2022-12-14T{time}:00+10:00")).unwrap(),
+            direction: Some(direction),
+            avg_speed,
+            gust_speed: None,
+        }
+    }
+
+    #[test]
+    fn forecast_accuracy() {
+        let forecast = [
+            observation("10:00", 8., 350),
+            observation("11:00", 6., 0),
+            observation("12:00", 4., 10),
+            observation("13:00", 9., 0),
+        ];
+        let observations = [
+            observation("09:50", 6., 10),
+            observation("10:10", 7., 350),
+            observation("11:00", 8., 20),
+            observation("12:20", 3., 0),
+        ];
+        let accuracy = Accuracy::new(&forecast, &observations, 6.).unwrap();
+
+        assert_eq!(3, accuracy.samples);
+        assert!((accuracy.speed_bias - 0.5 / 3.).abs() < 0.01);
+        assert!((accuracy.speed_mae - 4.5 / 3.).abs() < 0.01);
+        assert!((accuracy.direction_mae.unwrap() - 40. / 3.).abs() < 0.1);
+        assert_eq!(Some(1.), accuracy.hit_rate);
+        assert_eq!(Some(0.), accuracy.false_alarm_rate);
+
+        assert_eq!(
+            "Forecast accuracy for rvs (30 days, 3 hours, 24h ahead)\n\
+            Speed bias +0.2 m/s\n\
+            Speed MAE 1.5 m/s\n\
+            Direction MAE 13°\n\
+            Good wind hit rate 100%, false alarms 0%",
+            accuracy.format("rvs", 30, SpeedUnit::MetersPerSecond)
+        );
+    }
+
+    #[test]
+    fn calm_observations() {
+        let forecast = [observation("10:00", 3., 90)];
+        let mut observations = [observation("10:00", 0.5, 0)];
+        observations[0].direction = None;
+        let accuracy = Accuracy::new(&forecast, &observations, 6.).unwrap();
+
+        assert_eq!(None, accuracy.direction_mae);
+        assert!(accuracy
+            .format("rvs", 30, SpeedUnit::MetersPerSecond)
+            .contains("Direction MAE n/a\n"));
+    }
+
+    #[test]
+    fn no_matching_observations() {
+        let forecast = [observation("10:00", 8., 0)];
+        let observations = [observation("12:00", 8., 0)];
+        assert!(Accuracy::new(&forecast, &observations, 6.).is_none());
+    }
+
+    #[test]
+    fn circular_direction() {
+        assert!(angle_between(0., mean_direction([350, 10]).unwrap()) < 0.01);
+        assert_eq!(None, mean_direction([]));
+        assert_eq!(20., angle_between(350., 10.));
+        assert_eq!(180., angle_between(90., 270.));
+    }
+}
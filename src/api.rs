@@ -0,0 +1,299 @@
+//! REST API for dashboards
+//!
+//! All endpoints require `Authorization: Bearer <token>` header. Speeds are given in m/s, time in RFC 3339.
+//!
+//! - `GET /api/spots` – spots (sources) having observations;
+//! - `GET /api/spots/:spot/observations?from=&to=` – observations in `[from, to)` (last 24 hours by default);
+//! - `GET /api/spots/:spot/observations/latest` – most recent observation;
+//! - `GET /api/trackers` – current wind state of every subscriber tracker;
+//! - `GET /api/subscriptions`, `GET /api/subscriptions/:user_id` – subscriptions;
+//! - `PUT /api/subscriptions/:user_id` – subscribes a user;
+//! - `PATCH /api/subscriptions/:user_id` – updates subscription settings (`rule`, `threshold`, `speed_unit`,
+//!   `digest_at`, `forecast_at`). `null` resets a setting to default;
+//! - `DELETE /api/subscriptions/:user_id` – unsubscribes a user.
+use crate::{
+    history::History, parser::Observation, prelude::*, rule::Rule, units::SpeedUnit, Shared,
+    Subscription, Subscriptions, WindState,
+};
+use axum::{
+    extract::{Path, Query},
+    http::{header, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Duration, FixedOffset, NaiveTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc};
+
+/// Shared state API is serving
+pub struct Api {
+    /// Bearer token clients should provide
+    pub token: String,
+    pub subscriptions: Shared<Subscriptions>,
+    pub history: Shared<History>,
+    /// Current state of subscriber trackers by user id
+    pub trackers: Shared<HashMap<i64, WindState>>,
+}
+
+pub fn router(api: Arc<Api>) -> Router {
+    Router::new()
+        .route("/spots", get(list_spots))
+        .route("/spots/:spot/observations", get(list_observations))
+        .route("/spots/:spot/observations/latest", get(latest_observation))
+        .route("/trackers", get(list_trackers))
+        .route("/subscriptions", get(list_subscriptions))
+        .route(
+            "/subscriptions/:user_id",
+            get(get_subscription)
+                .put(create_subscription)
+                .patch(update_subscription)
+                .delete(remove_subscription),
+        )
+        .route_layer(middleware::from_fn(authorize))
+        .layer(Extension(api))
+}
+
+async fn authorize<B>(request: Request<B>, next: Next<B>) -> Response {
+    let api: &Arc<Api> = request.extensions().get().unwrap();
+    let expected = format!("Bearer {}", api.token);
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .map_or(false, |value| {
+            constant_time_eq(value.as_bytes(), expected.as_bytes())
+        });
+    if authorized {
+        next.run(request).await
+    } else {
+        ApiError::Unauthorized.into_response()
+    }
+}
+
+/// Compares byte strings in time not depending on their content, so the token can't be guessed byte by byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Runs database work on the blocking thread pool, so slow queries don't stall the async runtime
+async fn blocking<T, F>(api: Arc<Api>, f: F) -> std::result::Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&Api) -> std::result::Result<T, ApiError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f(&api))
+        .await
+        .map_err(|e| ApiError::Internal(e.into()))?
+}
+
+enum ApiError {
+    Unauthorized,
+    NotFound,
+    BadRequest(String),
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        ApiError::Internal(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
+            ApiError::NotFound => (StatusCode::NOT_FOUND, "Not found".to_string()),
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::Internal(e) => {
+                error!("{:?}", e);
+                let message = "Internal error".to_string();
+                (StatusCode::INTERNAL_SERVER_ERROR, message)
+            }
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<Json<T>, ApiError>;
+
+async fn list_spots(Extension(api): Extension<Arc<Api>>) -> ApiResult<Vec<String>> {
+    let spots = blocking(api, |api| Ok(api.history.lock().unwrap().sources()?)).await?;
+    Ok(Json(spots))
+}
+
+#[derive(Deserialize)]
+struct TimeRange {
+    from: Option<DateTime<FixedOffset>>,
+    to: Option<DateTime<FixedOffset>>,
+}
+
+async fn list_observations(
+    Extension(api): Extension<Arc<Api>>,
+    Path(spot): Path<String>,
+    Query(range): Query<TimeRange>,
+) -> ApiResult<Vec<Observation>> {
+    let to = range.to.unwrap_or_else(|| Utc::now().into());
+    let from = range.from.unwrap_or(to - Duration::days(1));
+    let observations = blocking(api, move |api| {
+        Ok(api.history.lock().unwrap().list(&spot, &from, &to)?)
+    })
+    .await?;
+    Ok(Json(observations))
+}
+
+async fn latest_observation(
+    Extension(api): Extension<Arc<Api>>,
+    Path(spot): Path<String>,
+) -> ApiResult<Observation> {
+    let latest = blocking(api, move |api| {
+        Ok(api.history.lock().unwrap().latest(&spot)?)
+    })
+    .await?;
+    latest.map(Json).ok_or(ApiError::NotFound)
+}
+
+#[derive(Serialize)]
+struct TrackerState {
+    user_id: i64,
+    #[serde(flatten)]
+    state: WindState,
+}
+
+async fn list_trackers(Extension(api): Extension<Arc<Api>>) -> ApiResult<Vec<TrackerState>> {
+    let trackers = api.trackers.lock().unwrap();
+    let mut states = trackers
+        .iter()
+        .map(|(user_id, state)| TrackerState {
+            user_id: *user_id,
+            state: *state,
+        })
+        .collect::<Vec<_>>();
+    states.sort_by_key(|s| s.user_id);
+    Ok(Json(states))
+}
+
+async fn list_subscriptions(Extension(api): Extension<Arc<Api>>) -> ApiResult<Vec<Subscription>> {
+    let subscriptions = blocking(api, |api| {
+        Ok(api.subscriptions.lock().unwrap().list_subscriptions()?)
+    })
+    .await?;
+    Ok(Json(subscriptions))
+}
+
+async fn get_subscription(
+    Extension(api): Extension<Arc<Api>>,
+    Path(user_id): Path<i64>,
+) -> ApiResult<Subscription> {
+    let subscription = blocking(api, move |api| {
+        Ok(api
+            .subscriptions
+            .lock()
+            .unwrap()
+            .find_subscription(user_id)?)
+    })
+    .await?;
+    subscription.map(Json).ok_or(ApiError::NotFound)
+}
+
+async fn create_subscription(
+    Extension(api): Extension<Arc<Api>>,
+    Path(user_id): Path<i64>,
+) -> ApiResult<Subscription> {
+    let subscription = blocking(api, move |api| {
+        let mut subscriptions = api.subscriptions.lock().unwrap();
+        if subscriptions.find_subscription(user_id)?.is_none() {
+            subscriptions.new_subscription(user_id)?;
+        }
+        Ok(subscriptions.find_subscription(user_id)?)
+    })
+    .await?;
+    subscription.map(Json).ok_or(ApiError::NotFound)
+}
+
+/// Subscription settings to update. Missing fields are left intact, `null` fields are reset
+#[derive(Deserialize)]
+struct SubscriptionUpdate {
+    #[serde(default, deserialize_with = "nullable")]
+    rule: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    threshold: Option<Option<f32>>,
+    #[serde(default, deserialize_with = "nullable")]
+    speed_unit: Option<Option<SpeedUnit>>,
+    #[serde(default, deserialize_with = "nullable")]
+    digest_at: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    forecast_at: Option<Option<String>>,
+}
+
+/// Distinguishes `null` (`Some(None)`) from a missing field (`None`)
+fn nullable<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Option<T>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+fn parse_time(time: Option<String>) -> std::result::Result<Option<NaiveTime>, ApiError> {
+    time.map(|time| {
+        NaiveTime::parse_from_str(&time, "%H:%M")
+            .map_err(|_| ApiError::BadRequest(format!("Invalid time '{}'. Use HH:MM", time)))
+    })
+    .transpose()
+}
+
+async fn update_subscription(
+    Extension(api): Extension<Arc<Api>>,
+    Path(user_id): Path<i64>,
+    Json(update): Json<SubscriptionUpdate>,
+) -> ApiResult<Subscription> {
+    // Validating everything first, so invalid request doesn't leave subscription partially updated
+    let rule = update
+        .rule
+        .map(|rule| rule.map(|rule| rule.parse::<Rule>()).transpose())
+        .transpose()
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let digest_at = update.digest_at.map(parse_time).transpose()?;
+    let forecast_at = update.forecast_at.map(parse_time).transpose()?;
+
+    let subscription = blocking(api, move |api| {
+        let mut subscriptions = api.subscriptions.lock().unwrap();
+        if subscriptions.find_subscription(user_id)?.is_none() {
+            return Err(ApiError::NotFound);
+        }
+        if let Some(rule) = rule {
+            subscriptions.set_rule(user_id, rule.as_ref())?;
+        }
+        if let Some(threshold) = update.threshold {
+            subscriptions.set_threshold(user_id, threshold)?;
+        }
+        if let Some(unit) = update.speed_unit {
+            subscriptions.set_speed_unit(user_id, unit.unwrap_or_default())?;
+        }
+        if let Some(time) = digest_at {
+            subscriptions.set_digest_time(user_id, time)?;
+        }
+        if let Some(time) = forecast_at {
+            subscriptions.set_forecast_time(user_id, time)?;
+        }
+        Ok(subscriptions.find_subscription(user_id)?)
+    })
+    .await?;
+    subscription.map(Json).ok_or(ApiError::NotFound)
+}
+
+async fn remove_subscription(
+    Extension(api): Extension<Arc<Api>>,
+    Path(user_id): Path<i64>,
+) -> std::result::Result<StatusCode, ApiError> {
+    blocking(api, move |api| {
+        let mut subscriptions = api.subscriptions.lock().unwrap();
+        if subscriptions.find_subscription(user_id)?.is_none() {
+            return Err(ApiError::NotFound);
+        }
+        subscriptions.remove_subscription(user_id)?;
+        Ok(StatusCode::NO_CONTENT)
+    })
+    .await
+}
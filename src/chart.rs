@@ -0,0 +1,183 @@
+//! Rendering of statistics charts as PNG images
+//!
+//! Charts are drawn pixel by pixel without text (captions are sent along with the images), so no font or graphics
+//! library is required.
+use crate::{prelude::*, stats::WindRose};
+
+type Rgb = [u8; 3];
+
+const BACKGROUND: Rgb = [255, 255, 255];
+const GRID: Rgb = [210, 210, 210];
+const AXIS: Rgb = [90, 90, 90];
+const BAR: Rgb = [33, 113, 181];
+
+/// Colors of wind rose speed bins from light to strong wind
+const SPEED_COLORS: [Rgb; 5] = [
+    [198, 219, 239],
+    [107, 174, 214],
+    [33, 113, 181],
+    [253, 141, 60],
+    [215, 48, 31],
+];
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<Rgb>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![BACKGROUND; (width * height) as usize],
+        }
+    }
+
+    fn set(&mut self, x: i64, y: i64, color: Rgb) {
+        if (0..self.width as i64).contains(&x) && (0..self.height as i64).contains(&y) {
+            self.pixels[(y as u32 * self.width + x as u32) as usize] = color;
+        }
+    }
+
+    fn fill_rect(&mut self, x: i64, y: i64, width: i64, height: i64, color: Rgb) {
+        for y in y..y + height {
+            for x in x..x + width {
+                self.set(x, y, color);
+            }
+        }
+    }
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut png = vec![];
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels.concat())?;
+        writer.finish()?;
+        Ok(png)
+    }
+}
+
+/// Wind rose: a petal per compass direction, stacked by speed bins. Rings are drawn every quarter of the longest
+/// petal, north is up
+pub fn wind_rose(rose: &WindRose) -> Result<Vec<u8>> {
+    const SIZE: u32 = 400;
+    const RADIUS: f32 = 180.;
+    let mut canvas = Canvas::new(SIZE, SIZE);
+    let center = SIZE as f32 / 2.;
+
+    let longest = rose
+        .counts
+        .iter()
+        .map(|counts| counts.iter().sum::<u32>())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let scale = RADIUS / longest as f32;
+
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let (dx, dy) = (x as f32 + 0.5 - center, y as f32 + 0.5 - center);
+            let r = dx.hypot(dy);
+            if r > RADIUS + 1. {
+                continue;
+            }
+            // clockwise from north
+            let angle = dx.atan2(-dy).to_degrees().rem_euclid(360.);
+            let point = (angle / 22.5).round() as usize % 16;
+            let offset = (angle - point as f32 * 22.5 + 180.).rem_euclid(360.) - 180.;
+
+            let mut color = None;
+            if offset.abs() < 9. {
+                let mut length = 0.;
+                for (count, bin_color) in rose.counts[point].iter().zip(SPEED_COLORS) {
+                    length += *count as f32 * scale;
+                    if r < length {
+                        color = Some(bin_color);
+                        break;
+                    }
+                }
+            }
+            let on_ring = (1..=4).any(|ring| (r - RADIUS * ring as f32 / 4.).abs() < 0.6);
+            let on_axis = dx.abs() < 0.6 || dy.abs() < 0.6;
+            let color = match color {
+                Some(color) => color,
+                None if on_ring || on_axis => GRID,
+                None => continue,
+            };
+            canvas.set(x as i64, y as i64, color);
+        }
+    }
+    // north marker
+    for row in 0..8i64 {
+        canvas.fill_rect(center as i64 - row / 2, row + 2, row + 1, 1, AXIS);
+    }
+    canvas.encode()
+}
+
+/// Bar chart of percentages. Grid lines are drawn every 10%
+pub fn bars(values: &[f32]) -> Result<Vec<u8>> {
+    const WIDTH: u32 = 480;
+    const HEIGHT: u32 = 240;
+    const MARGIN: i64 = 10;
+    let mut canvas = Canvas::new(WIDTH, HEIGHT);
+    let plot_height = HEIGHT as i64 - 2 * MARGIN;
+    let plot_width = WIDTH as i64 - 2 * MARGIN;
+
+    // scale is rounded up to the next 10%
+    let max = values.iter().copied().fold(0f32, f32::max);
+    let max = ((max / 10.).ceil() * 10.).clamp(10., 100.);
+    let y = |value: f32| MARGIN + plot_height - (value / max * plot_height as f32).round() as i64;
+
+    let mut line = 0.;
+    while line <= max {
+        canvas.fill_rect(MARGIN, y(line), plot_width, 1, GRID);
+        line += 10.;
+    }
+    let slot = plot_width / values.len().max(1) as i64;
+    for (idx, value) in values.iter().enumerate() {
+        let top = y(*value);
+        let x = MARGIN + idx as i64 * slot + slot / 8;
+        canvas.fill_rect(x, top, slot - slot / 4, y(0.) - top, BAR);
+    }
+    canvas.fill_rect(MARGIN, y(0.), plot_width, 1, AXIS);
+    canvas.encode()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    /// Decodes PNG image returning its width and color of a pixel
+    fn pixel(png: &[u8], x: usize, y: usize) -> Result<(u32, Rgb)> {
+        let mut reader = png::Decoder::new(png).read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let idx = (y * info.width as usize + x) * 3;
+        Ok((info.width, [buf[idx], buf[idx + 1], buf[idx + 2]]))
+    }
+
+    #[test]
+    fn png_images() -> Result<()> {
+        let mut rose = WindRose::default();
+        rose.counts[0] = [1, 2, 3, 0, 1];
+        rose.counts[6] = [2, 0, 0, 0, 0];
+        rose.total = 9;
+        let rose = wind_rose(&rose)?;
+        // north petal: the first bin is 1/7 of the radius
+        assert_eq!((400, SPEED_COLORS[0]), pixel(&rose, 200, 180)?);
+        assert_eq!((400, SPEED_COLORS[4]), pixel(&rose, 200, 25)?);
+        // no south petal
+        assert_eq!((400, BACKGROUND), pixel(&rose, 205, 300)?);
+
+        let bars = bars(&[10., 55.5, 0.])?;
+        // the second bar reaches 55.5% of 60% scale
+        assert_eq!((480, BAR), pixel(&bars, 240, 40)?);
+        assert_eq!((480, BACKGROUND), pixel(&bars, 240, 20)?);
+        Ok(())
+    }
+}
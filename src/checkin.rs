@@ -0,0 +1,160 @@
+//! Check-ins of riders to sessions ("Going", "At the spot", "Not today")
+//!
+//! Alert messages sent during a session carry inline buttons. Each rider has a single check-in per session, so
+//! pressing another button changes it. Buttons pass session id and status as callback data (`checkin:<id>:<status>`).
+use crate::prelude::*;
+use anyhow::anyhow;
+use std::{fmt::Write, str::FromStr};
+
+const CALLBACK_PREFIX: &str = "checkin";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CheckIn {
+    Going,
+    AtSpot,
+    NotToday,
+}
+
+impl CheckIn {
+    pub const ALL: [CheckIn; 3] = [CheckIn::Going, CheckIn::AtSpot, CheckIn::NotToday];
+
+    /// Button label
+    pub fn label(self) -> &'static str {
+        match self {
+            CheckIn::Going => "Going",
+            CheckIn::AtSpot => "At the spot",
+            CheckIn::NotToday => "Not today",
+        }
+    }
+
+    /// Name check-in is stored and passed in callback data with
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CheckIn::Going => "going",
+            CheckIn::AtSpot => "at_spot",
+            CheckIn::NotToday => "not_today",
+        }
+    }
+
+    pub fn callback_data(self, session_id: i32) -> String {
+        format!("{}:{}:{}", CALLBACK_PREFIX, session_id, self.as_str())
+    }
+
+    /// Parses callback data of a check-in button. Returns session id and check-in
+    pub fn parse_callback_data(data: &str) -> Option<(i32, CheckIn)> {
+        let mut parts = data.split(':');
+        if parts.next()? != CALLBACK_PREFIX {
+            return None;
+        }
+        let session_id = parts.next()?.parse().ok()?;
+        let check_in = parts.next()?.parse().ok()?;
+        parts.next().is_none().then_some((session_id, check_in))
+    }
+}
+
+impl FromStr for CheckIn {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        CheckIn::ALL
+            .into_iter()
+            .find(|c| c.as_str() == input)
+            .ok_or_else(|| anyhow!("Unknown check-in '{}'", input))
+    }
+}
+
+/// Names of riders checked in to a session by check-in
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CheckIns(pub Vec<(CheckIn, Vec<String>)>);
+
+impl CheckIns {
+    /// Groups (name, check-in) pairs ordered by check-in time
+    pub fn new(check_ins: impl IntoIterator<Item = (String, CheckIn)>) -> Self {
+        let mut grouped = CheckIn::ALL.map(|c| (c, vec![])).to_vec();
+        for (name, check_in) in check_ins {
+            if let Some((_, names)) = grouped.iter_mut().find(|(c, _)| *c == check_in) {
+                names.push(name);
+            }
+        }
+        Self(grouped)
+    }
+
+    pub fn count(&self, check_in: CheckIn) -> usize {
+        self.names(check_in).len()
+    }
+
+    pub fn names(&self, check_in: CheckIn) -> &[String] {
+        self.0
+            .iter()
+            .find(|(c, _)| *c == check_in)
+            .map(|(_, names)| names.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Lines listing riders going and at the spot, and number of riders not going. Empty if nobody checked in
+    pub fn format(&self) -> String {
+        let mut result = String::new();
+        for check_in in [CheckIn::Going, CheckIn::AtSpot] {
+            let names = self.names(check_in);
+            if !names.is_empty() {
+                let _ = writeln!(
+                    result,
+                    "{} ({}): {}",
+                    check_in.label(),
+                    names.len(),
+                    names.join(", ")
+                );
+            }
+        }
+        let not_today = self.count(CheckIn::NotToday);
+        if not_today > 0 {
+            let _ = writeln!(result, "{} ({})", CheckIn::NotToday.label(), not_today);
+        }
+        result.trim_end().to_string()
+    }
+}
+
+/// Alert message text with check-ins appended
+pub fn alert_text(alert: &str, check_ins: &CheckIns) -> String {
+    match check_ins.format() {
+        summary if summary.is_empty() => alert.to_string(),
+        summary => format!("{}\n\n{}", alert, summary),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn callback_data() {
+        let data = CheckIn::AtSpot.callback_data(42);
+        assert_eq!("checkin:42:at_spot", data);
+        assert_eq!(
+            Some((42, CheckIn::AtSpot)),
+            CheckIn::parse_callback_data(&data)
+        );
+        assert_eq!(None, CheckIn::parse_callback_data("checkin:42:maybe"));
+        assert_eq!(None, CheckIn::parse_callback_data("other:42:going"));
+        assert_eq!(None, CheckIn::parse_callback_data("checkin:42:going:1"));
+    }
+
+    #[test]
+    fn check_ins_summary() {
+        let alert = "Wind is growing up: 12:00 7.0 m/s NW ↘ (315°)";
+        assert_eq!(alert, alert_text(alert, &CheckIns::default()));
+
+        let check_ins = CheckIns::new([
+            ("Alex".to_string(), CheckIn::Going),
+            ("Maria".to_string(), CheckIn::NotToday),
+            ("Ivan".to_string(), CheckIn::Going),
+        ]);
+        assert_eq!(2, check_ins.count(CheckIn::Going));
+        assert_eq!(0, check_ins.count(CheckIn::AtSpot));
+        assert_eq!(
+            format!("{alert}\n\nGoing (2): Alex, Ivan\nNot today (1)"),
+            alert_text(alert, &check_ins)
+        );
+    }
+}
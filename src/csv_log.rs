@@ -0,0 +1,111 @@
+//! Observations from CSV logs
+use crate::{
+    parser::{parse_rows, Columns, ParseError, Parsed, StationClock},
+    units::SpeedUnit,
+};
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct CsvLog {
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    /// Whether the first line is a header. Required if columns are given by header text
+    #[serde(default = "default_has_header")]
+    pub has_header: bool,
+    #[serde(default)]
+    pub columns: Columns,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_has_header() -> bool {
+    true
+}
+
+impl Default for CsvLog {
+    fn default() -> Self {
+        Self {
+            delimiter: default_delimiter(),
+            has_header: default_has_header(),
+            columns: Columns::default(),
+        }
+    }
+}
+
+impl CsvLog {
+    /// Parses log lines. Speeds in the log are given in `unit`
+    pub fn parse(
+        &self,
+        input: &str,
+        clock: &StationClock,
+        unit: SpeedUnit,
+    ) -> Result<Parsed, ParseError> {
+        let delimiter = u8::try_from(self.delimiter).map_err(|_| {
+            ParseError::Malformed(format!("Non-ASCII delimiter '{}'", self.delimiter))
+        })?;
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(self.has_header)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(input.as_bytes());
+
+        let header = if self.has_header {
+            let header = reader
+                .headers()
+                .map_err(|e| ParseError::Malformed(e.to_string()))?;
+            header.iter().map(str::to_string).collect()
+        } else {
+            vec![]
+        };
+        let layout = self.columns.resolve(&header)?;
+
+        let rows = reader
+            .records()
+            .map(|record| record.map_err(|e| ParseError::Malformed(e.to_string())))
+            .map(|record| Ok(record?.iter().map(str::to_string).collect()))
+            .collect::<Result<Vec<Vec<String>>, ParseError>>()?;
+        Ok(parse_rows(rows, &layout, clock, unit))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::parser::ColumnRef;
+    use insta::assert_yaml_snapshot;
+
+    #[test]
+    fn csv_log() {
+        let input = include_str!("../tests/example.csv");
+        let log = CsvLog {
+            delimiter: ';',
+            has_header: true,
+            columns: Columns {
+                time: ColumnRef::Header("timestamp".to_string()),
+                direction: ColumnRef::Header("dir_deg".to_string()),
+                avg_speed: ColumnRef::Header("wind_avg".to_string()),
+                gust_speed: Some(ColumnRef::Header("wind_max".to_string())),
+            },
+        };
+        let clock = StationClock {
+            date_format: "%Y-%m-%dT%H:%M".to_string(),
+            ..StationClock::default()
+        };
+        let parsed = log
+            .parse(input, &clock, SpeedUnit::KilometersPerHour)
+            .unwrap();
+        assert_eq!(
+            vec!["Row 2: invalid direction 'n/a'"],
+            parsed
+                .errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        );
+        assert_yaml_snapshot!(parsed.observations);
+    }
+}
@@ -0,0 +1,210 @@
+//! Daily wind summary
+use crate::{
+    compass_point_index, compass_point_name, parser::Observation, rule::Rule, units::SpeedUnit,
+};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveTime, Timelike};
+use std::fmt::Write;
+
+/// Maximum gap (minutes) between two matching observations for them to be considered a single time range
+const MAX_GAP_MINUTES: i64 = 15;
+
+pub struct DailySummary {
+    pub date: NaiveDate,
+    pub max_speed: f32,
+    pub avg_speed: f32,
+    pub max_gust: Option<f32>,
+    /// Direction (16-point compass point angle) wind was blowing from most of the time. `None` if wind was calm
+    /// or variable all day
+    pub dominant_direction: Option<u16>,
+    /// Time ranges observations were matching the rule
+    pub good_wind: Vec<(DateTime<FixedOffset>, DateTime<FixedOffset>)>,
+}
+
+impl DailySummary {
+    /// Summarizes observations ordered by time. Returns `None` if there are no observations
+    pub fn new(date: NaiveDate, observations: &[Observation], rule: &Rule) -> Option<Self> {
+        if observations.is_empty() {
+            return None;
+        }
+        let max_speed = observations.iter().map(|o| o.avg_speed).fold(0., f32::max);
+        let avg_speed =
+            observations.iter().map(|o| o.avg_speed).sum::<f32>() / observations.len() as f32;
+        let max_gust = observations
+            .iter()
+            .filter_map(|o| o.gust_speed)
+            .reduce(f32::max);
+
+        let dominant_direction = dominant_direction(observations);
+
+        let good_wind = time_ranges(observations, rule, Duration::minutes(MAX_GAP_MINUTES));
+
+        Some(Self {
+            date,
+            max_speed,
+            avg_speed,
+            max_gust,
+            dominant_direction,
+            good_wind,
+        })
+    }
+
+    pub fn format(&self, unit: SpeedUnit) -> String {
+        let mut result = format!("Wind summary for {}\n", self.date.format("%d.%m.%Y"));
+        write!(
+            result,
+            "Max {}, average {}",
+            unit.format(self.max_speed),
+            unit.format(self.avg_speed)
+        )
+        .unwrap();
+        if let Some(gust) = self.max_gust {
+            write!(result, ", gusts up to {}", unit.format(gust)).unwrap();
+        }
+        writeln!(result).unwrap();
+        match self.dominant_direction {
+            Some(direction) => writeln!(result, "Mostly {}", compass_point_name(direction)),
+            None => writeln!(result, "Calm or variable direction"),
+        }
+        .unwrap();
+        if self.good_wind.is_empty() {
+            result.push_str("No good wind");
+        } else {
+            let ranges = self
+                .good_wind
+                .iter()
+                .map(|(from, to)| {
+                    // range of a single observation is shown as its time
+                    if from == to {
+                        from.format("%H:%M").to_string()
+                    } else {
+                        format!("{}–{}", from.format("%H:%M"), to.format("%H:%M"))
+                    }
+                })
+                .collect::<Vec<_>>();
+            write!(result, "Good wind: {}", ranges.join(", ")).unwrap();
+        }
+        result
+    }
+}
+
+/// Direction (16-point compass point angle) wind was blowing from most of the time. `None` if wind was calm or
+/// variable all the time
+pub fn dominant_direction(observations: &[Observation]) -> Option<u16> {
+    let mut directions = [0u32; 16];
+    for direction in observations.iter().filter_map(|o| o.direction) {
+        directions[compass_point_index(direction)] += 1;
+    }
+    most_frequent_point(&directions)
+}
+
+/// Angle of the compass point having the largest count. `None` if all counts are zero
+pub(crate) fn most_frequent_point(counts: &[u32; 16]) -> Option<u16> {
+    counts
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .max_by_key(|(_, count)| **count)
+        .map(|(dominant, _)| (dominant as f32 * 22.5).round() as u16)
+}
+
+/// Time ranges (first and last observation) of consecutive observations matching a rule
+///
+/// Observations should be ordered by time. Range is split if there is a gap between matching observations
+/// larger than `max_gap`.
+pub fn time_ranges(
+    observations: &[Observation],
+    rule: &Rule,
+    max_gap: Duration,
+) -> Vec<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let mut ranges = vec![];
+    let mut range: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> = None;
+    for observation in observations {
+        if !rule.test(observation) {
+            ranges.extend(range.take());
+            continue;
+        }
+        range = match range {
+            Some((from, to)) if observation.time - to <= max_gap => Some((from, observation.time)),
+            range => {
+                ranges.extend(range);
+                Some((observation.time, observation.time))
+            }
+        }
+    }
+    ranges.extend(range);
+    ranges
+}
+
+/// Digests sent in the morning summarize previous day, digests sent after noon summarize the current day
+pub fn summarizes_previous_day(digest_at: NaiveTime) -> bool {
+    digest_at.hour() < 12
+}
+
+/// Date digest sent at a given time should summarize
+pub fn digest_date(today: NaiveDate, digest_at: NaiveTime) -> NaiveDate {
+    if summarizes_previous_day(digest_at) {
+        today.pred()
+    } else {
+        today
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::Sector;
+
+    fn observations(data: &[(&str, f32, u16)]) -> Vec<Observation> {
+        data.iter()
+            .map(|(time, avg_speed, direction)| Observation {
+                time: DateTime::parse_from_rfc3339(&format!("2022-10-29T{time}:00+10:00")).unwrap(),
+                direction: Some(*direction),
+                avg_speed: *avg_speed,
+                gust_speed: Some(avg_speed * 1.5),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn daily_summary() {
+        let date = NaiveDate::from_ymd(2022, 10, 29);
+        let observations = observations(&[
+            ("10:00", 3., 310),
+            ("10:30", 6., 315),
+            ("11:00", 7., 320),
+            ("11:10", 8., 300),
+            ("12:00", 6., 310),
+            ("12:30", 4., 180),
+            ("13:00", 6., 315),
+        ]);
+        let rule = Rule::speed_in_sector(5., Sector::NORTH_180);
+        let summary = DailySummary::new(date, &observations, &rule).unwrap();
+
+        assert_eq!(8., summary.max_speed);
+        assert_eq!(Some(12.), summary.max_gust);
+        assert_eq!(Some(315), summary.dominant_direction);
+        assert_eq!(
+            "Wind summary for 29.10.2022\n\
+            Max 8.0 m/s, average 5.7 m/s, gusts up to 12.0 m/s\n\
+            Mostly NW\n\
+            Good wind: 10:30, 11:00–11:10, 12:00, 13:00",
+            summary.format(SpeedUnit::MetersPerSecond)
+        );
+    }
+
+    #[test]
+    fn empty_summary() {
+        let date = NaiveDate::from_ymd(2022, 10, 29);
+        let rule = Rule::speed_in_sector(5., Sector::NORTH_180);
+        assert!(DailySummary::new(date, &[], &rule).is_none());
+    }
+
+    #[test]
+    fn digest_day() {
+        let today = NaiveDate::from_ymd(2022, 10, 29);
+        let yesterday = NaiveDate::from_ymd(2022, 10, 28);
+        assert_eq!(yesterday, digest_date(today, NaiveTime::from_hms(8, 0, 0)));
+        assert_eq!(today, digest_date(today, NaiveTime::from_hms(21, 30, 0)));
+    }
+}
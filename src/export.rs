@@ -0,0 +1,190 @@
+//! Export of observation history for analysis in notebooks
+//!
+//! Speeds are exported in m/s and times in RFC 3339 with the station offset. Columnar format is a single JSON object
+//! with an array per field (`pandas.DataFrame(json.load(f))` reads it as is).
+use crate::{accuracy::mean_direction, parser::Observation, prelude::*};
+use anyhow::bail;
+use chrono::{DateTime, Duration, FixedOffset, TimeZone};
+use serde::Serialize;
+use std::{
+    fmt::{self, Display},
+    io::Write,
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    JsonLines,
+    Columnar,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        use ExportFormat::*;
+        let format = match input.trim().to_lowercase().as_str() {
+            "csv" => Csv,
+            "jsonl" | "ndjson" | "json-lines" => JsonLines,
+            "columnar" | "columns" => Columnar,
+            _ => bail!(
+                "Unknown export format '{}'. Use csv, jsonl or columnar",
+                input
+            ),
+        };
+        Ok(format)
+    }
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::Columnar => "columnar",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Serialize, Default)]
+struct Columns {
+    time: Vec<DateTime<FixedOffset>>,
+    direction: Vec<Option<u16>>,
+    avg_speed: Vec<f32>,
+    gust_speed: Vec<Option<f32>>,
+}
+
+/// Writes observations in a given format
+pub fn export(
+    observations: &[Observation],
+    format: ExportFormat,
+    mut out: impl Write,
+) -> Result<()> {
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            for observation in observations {
+                writer.serialize(observation)?;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::JsonLines => {
+            for observation in observations {
+                serde_json::to_writer(&mut out, observation)?;
+                writeln!(out)?;
+            }
+        }
+        ExportFormat::Columnar => {
+            let mut columns = Columns::default();
+            for observation in observations {
+                columns.time.push(observation.time);
+                columns.direction.push(observation.direction);
+                columns.avg_speed.push(observation.avg_speed);
+                columns.gust_speed.push(observation.gust_speed);
+            }
+            serde_json::to_writer(&mut out, &columns)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Aggregates observations ordered by time into periods of a given length
+///
+/// Periods are aligned to the unix epoch (so 10 minute periods start at :00, :10...) and marked with their start.
+/// Average speed is averaged, gust is the maximum one and direction is the circular mean of known directions.
+pub fn resample(observations: &[Observation], period: Duration) -> Vec<Observation> {
+    let period_secs = period.num_seconds().max(1);
+    let mut resampled = vec![];
+    let mut start = 0;
+    while start < observations.len() {
+        let bucket = observations[start].time.timestamp().div_euclid(period_secs);
+        let len = observations[start..]
+            .iter()
+            .take_while(|o| o.time.timestamp().div_euclid(period_secs) == bucket)
+            .count();
+        let group = &observations[start..start + len];
+        start += len;
+
+        let offset = *group[0].time.offset();
+        let direction = mean_direction(group.iter().filter_map(|o| o.direction))
+            .map(|d| d.round() as u16 % 360);
+        let gust_speed = group
+            .iter()
+            .filter_map(|o| o.gust_speed)
+            .fold(None, |max: Option<f32>, gust| {
+                Some(max.map_or(gust, |m| m.max(gust)))
+            });
+        resampled.push(Observation {
+            time: offset.timestamp(bucket * period_secs, 0),
+            direction,
+            avg_speed: group.iter().map(|o| o.avg_speed).sum::<f32>() / len as f32,
+            gust_speed,
+        });
+    }
+    resampled
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn observation(time: &str, direction: Option<u16>, avg: f32, gust: Option<f32>) -> Observation {
+        Observation {
+            time: DateTime::parse_from_rfc3339(&format!("2022-10-29T{time}:00+10:00")).unwrap(),
+            direction,
+            avg_speed: avg,
+            gust_speed: gust,
+        }
+    }
+
+    #[test]
+    fn resampling() {
+        let observations = vec![
+            observation("12:01", Some(350), 4., Some(6.)),
+            observation("12:05", Some(10), 6., None),
+            observation("12:09", None, 5., Some(8.)),
+            observation("12:12", None, 0., None),
+        ];
+        let resampled = resample(&observations, Duration::minutes(10));
+        assert_eq!(2, resampled.len());
+        assert_eq!("12:00 5.0 m/s N  ↓ (  0°)", resampled[0].to_string());
+        assert_eq!(Some(8.), resampled[0].gust_speed);
+        assert_eq!("12:10 0.0 m/s calm", resampled[1].to_string());
+        assert_eq!(None, resampled[1].gust_speed);
+    }
+
+    #[test]
+    fn formats() -> Result<()> {
+        let observations = vec![
+            observation("12:00", Some(90), 5., Some(7.5)),
+            observation("12:10", None, 0., None),
+        ];
+        let export = |format: &str| -> Result<String> {
+            let mut out = vec![];
+            export(&observations, format.parse()?, &mut out)?;
+            Ok(String::from_utf8(out)?)
+        };
+        assert_eq!(
+            "time,direction,avg_speed,gust_speed\n\
+            2022-10-29T12:00:00+10:00,90,5.0,7.5\n\
+            2022-10-29T12:10:00+10:00,,0.0,\n",
+            export("csv")?
+        );
+        assert_eq!(
+            "{\"time\":\"2022-10-29T12:00:00+10:00\",\"direction\":90,\"avg_speed\":5.0,\"gust_speed\":7.5}\n\
+            {\"time\":\"2022-10-29T12:10:00+10:00\",\"direction\":null,\"avg_speed\":0.0,\"gust_speed\":null}\n",
+            export("jsonl")?
+        );
+        assert_eq!(
+            "{\"time\":[\"2022-10-29T12:00:00+10:00\",\"2022-10-29T12:10:00+10:00\"],\"direction\":[90,null],\
+            \"avg_speed\":[5.0,0.0],\"gust_speed\":[7.5,null]}\n",
+            export("columnar")?
+        );
+        Ok(())
+    }
+}
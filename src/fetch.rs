@@ -0,0 +1,211 @@
+//! Reading raw data of observation sources
+//!
+//! Source URL is either HTTP(S) URL, `file://` URL or a plain path. Local file is re-read on every fetch, which is
+//! useful for offline testing. Local directory is tailed: every fetch returns files not read yet or changed
+//! (by size or modification time) since they were read, so stations dropping files via rsync can be followed.
+//! Files are compared by name rather than against the newest modification time, because `rsync -t` keeps
+//! modification time of the source, so a file delivered late may be older than the ones already read. Hidden files
+//! (like rsync temporary files) are ignored.
+//!
+//! HTTP requests are conditional (`If-None-Match`, `If-Modified-Since`), so unchanged pages are not downloaded
+//! again if the station supports it.
+use crate::prelude::*;
+use anyhow::Context;
+use reqwest::{
+    header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, Proxy, StatusCode, Url,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::fs;
+
+/// User agent of HTTP requests by default
+pub const USER_AGENT: &str = concat!("telewind/", env!("CARGO_PKG_VERSION"));
+
+/// Creates HTTP client shared by all requests
+///
+/// Proxy is taken from `HTTP_PROXY`/`HTTPS_PROXY` environment variables if not given.
+pub fn http_client(timeout: Duration, user_agent: &str, proxy: Option<&str>) -> Result<Client> {
+    let mut builder = Client::builder()
+        .timeout(timeout)
+        .connect_timeout(timeout.min(Duration::from_secs(10)))
+        .user_agent(user_agent);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    Ok(builder.build()?)
+}
+
+pub enum Fetcher {
+    Http {
+        client: Client,
+        url: String,
+        /// Validators of the last response sent with the next request
+        etag: Option<HeaderValue>,
+        last_modified: Option<HeaderValue>,
+    },
+    File(PathBuf),
+    Directory {
+        path: PathBuf,
+        /// Size and modification time of files read by name
+        read: HashMap<OsString, (u64, SystemTime)>,
+    },
+}
+
+impl Fetcher {
+    pub fn new(url: &str, client: &Client) -> Self {
+        let path = match Url::parse(url) {
+            Ok(url) if url.scheme() == "file" => url.to_file_path().ok(),
+            Ok(_) => None,
+            Err(_) => Some(PathBuf::from(url)),
+        };
+        match path {
+            Some(path) if path.is_dir() => Fetcher::Directory {
+                path,
+                read: HashMap::new(),
+            },
+            Some(path) => Fetcher::File(path),
+            None => Fetcher::Http {
+                client: client.clone(),
+                url: url.to_string(),
+                etag: None,
+                last_modified: None,
+            },
+        }
+    }
+
+    /// Reads documents published since the previous fetch (oldest first)
+    pub async fn fetch(&mut self) -> Result<Vec<String>> {
+        match self {
+            Fetcher::Http {
+                client,
+                url,
+                etag,
+                last_modified,
+            } => {
+                let mut request = client.get(url.as_str());
+                if let Some(etag) = etag {
+                    request = request.header(IF_NONE_MATCH, etag.clone());
+                }
+                if let Some(last_modified) = last_modified {
+                    request = request.header(IF_MODIFIED_SINCE, last_modified.clone());
+                }
+                let response = request
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .context(ObservationsEndpointFailed(url.to_string()))?;
+                if response.status() == StatusCode::NOT_MODIFIED {
+                    trace!("{} is not modified", url);
+                    return Ok(vec![]);
+                }
+                *etag = response.headers().get(ETAG).cloned();
+                *last_modified = response.headers().get(LAST_MODIFIED).cloned();
+                Ok(vec![response.text().await?])
+            }
+            Fetcher::File(path) => Ok(vec![read_file(path).await?]),
+            Fetcher::Directory { path, read } => {
+                let mut files = vec![];
+                let mut present = HashSet::new();
+                let mut entries = fs::read_dir(&path)
+                    .await
+                    .context(ReadingFile(path_str(path)))?;
+                while let Some(entry) = entries.next_entry().await? {
+                    let name = entry.file_name();
+                    let metadata = entry.metadata().await?;
+                    if name.to_string_lossy().starts_with('.') || !metadata.is_file() {
+                        continue;
+                    }
+                    let version = (metadata.len(), metadata.modified()?);
+                    if read.get(&name) != Some(&version) {
+                        files.push((version.1, name.clone(), version));
+                    }
+                    present.insert(name);
+                }
+                // forgetting removed files, so the set doesn't grow forever
+                read.retain(|name, _| present.contains(name));
+                files.sort();
+
+                // file failed to be read is retried on the next fetch, others are still returned
+                let mut documents = vec![];
+                for (_, name, version) in files {
+                    match read_file(&path.join(&name)).await {
+                        Ok(document) => {
+                            documents.push(document);
+                            read.insert(name, version);
+                        }
+                        Err(e) => warn!("{:?}", e),
+                    }
+                }
+                Ok(documents)
+            }
+        }
+    }
+}
+
+async fn read_file(path: &Path) -> Result<String> {
+    fs::read_to_string(path)
+        .await
+        .context(ReadingFile(path_str(path)))
+}
+
+fn path_str(path: &Path) -> String {
+    path.display().to_string()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use filetime::FileTime;
+
+    #[tokio::test]
+    async fn directory_is_tailed() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("telewind-fetch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("1.csv"), "first")?;
+
+        let client = Client::new();
+        let mut fetcher = Fetcher::new(Url::from_file_path(&dir).unwrap().as_str(), &client);
+        assert!(matches!(fetcher, Fetcher::Directory { .. }));
+        assert_eq!(vec!["first"], fetcher.fetch().await?);
+        assert!(fetcher.fetch().await?.is_empty());
+
+        std::fs::write(dir.join("2.csv"), "second")?;
+        std::fs::write(dir.join(".3.csv.tmp"), "incomplete")?;
+        assert_eq!(vec!["second"], fetcher.fetch().await?);
+
+        // rsync keeps modification time of the source, so a file delivered late is older than ones read
+        let late = dir.join("0.csv");
+        std::fs::write(&late, "late")?;
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        filetime::set_file_mtime(&late, FileTime::from_system_time(hour_ago))?;
+        assert_eq!(vec!["late"], fetcher.fetch().await?);
+        assert!(fetcher.fetch().await?.is_empty());
+
+        // file which can't be read doesn't block others
+        std::fs::write(dir.join("3.csv"), [0xff, 0xfe])?;
+        std::fs::write(dir.join("4.csv"), "fourth")?;
+        assert_eq!(vec!["fourth"], fetcher.fetch().await?);
+        std::fs::write(dir.join("3.csv"), "third")?;
+        assert_eq!(vec!["third"], fetcher.fetch().await?);
+
+        std::fs::write(dir.join("2.csv"), "second updated")?;
+        assert_eq!(vec!["second updated"], fetcher.fetch().await?);
+
+        std::fs::remove_dir_all(&dir)?;
+        assert!(matches!(
+            Fetcher::new(dir.join("1.csv").to_str().unwrap(), &client),
+            Fetcher::File(_)
+        ));
+        assert!(matches!(
+            Fetcher::new("http://3volna.ru/anemometer/getwind?id=1", &client),
+            Fetcher::Http { .. }
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+//! Wind forecasts
+//!
+//! Forecasts are fetched from Open-Meteo compatible JSON API. Each forecasted hour is represented as an
+//! [`Observation`], so the same [`Rule`] can be tested against forecasts and real observations.
+//!
+//! Expected URL looks like: `https://api.open-meteo.com/v1/forecast?latitude=43.1&longitude=131.9&hourly=windspeed_10m,winddirection_10m,windgusts_10m&timezone=Asia/Vladivostok`
+use crate::{digest::time_ranges, parser::Observation, prelude::*, rule::Rule, units::SpeedUnit};
+use anyhow::{anyhow, Context};
+use chrono::{
+    DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone,
+    Timelike,
+};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+struct ForecastResponse {
+    #[serde(default)]
+    utc_offset_seconds: i32,
+    #[serde(default)]
+    hourly_units: HashMap<String, String>,
+    hourly: HourlyForecast,
+}
+
+#[derive(Deserialize)]
+struct HourlyForecast {
+    time: Vec<String>,
+    #[serde(alias = "windspeed_10m")]
+    wind_speed_10m: Vec<Option<f32>>,
+    #[serde(alias = "winddirection_10m")]
+    wind_direction_10m: Vec<Option<f32>>,
+    #[serde(alias = "windgusts_10m", default)]
+    wind_gusts_10m: Vec<Option<f32>>,
+}
+
+/// Parses hourly forecast. Hours with missing speed or direction are skipped
+pub fn parse_forecast(input: &str) -> Result<Vec<Observation>> {
+    let response: ForecastResponse = serde_json::from_str(input)?;
+    let offset = FixedOffset::east_opt(response.utc_offset_seconds)
+        .ok_or_else(|| anyhow!("Invalid UTC offset: {}", response.utc_offset_seconds))?;
+    // Open-Meteo uses km/h by default
+    let unit = ["wind_speed_10m", "windspeed_10m"]
+        .iter()
+        .find_map(|key| response.hourly_units.get(*key))
+        .map(|unit| unit.parse::<SpeedUnit>())
+        .transpose()?
+        .unwrap_or(SpeedUnit::KilometersPerHour);
+
+    let hourly = response.hourly;
+    let mut result = vec![];
+    for (i, time) in hourly.time.iter().enumerate() {
+        let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M")
+            .with_context(|| format!("Invalid forecast time: {}", time))?;
+        let time = offset
+            .from_local_datetime(&time)
+            .single()
+            .ok_or_else(|| anyhow!("Invalid forecast time: {}", time))?;
+        let speed = hourly.wind_speed_10m.get(i).copied().flatten();
+        let direction = hourly.wind_direction_10m.get(i).copied().flatten();
+        let gust = hourly.wind_gusts_10m.get(i).copied().flatten();
+        if let (Some(speed), Some(direction)) = (speed, direction) {
+            result.push(Observation {
+                time,
+                direction: Some(direction.round() as u16 % 360),
+                avg_speed: unit.to_ms(speed),
+                gust_speed: gust.map(|g| unit.to_ms(g)),
+            })
+        }
+    }
+    Ok(result)
+}
+
+pub async fn fetch_forecast(client: &Client, url: &str) -> Result<Vec<Observation>> {
+    let body = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .context(ForecastEndpointFailed(url.to_string()))?
+        .text()
+        .await?;
+    parse_forecast(&body)
+}
+
+/// Forecasted hours with good wind
+pub struct GoodWindForecast {
+    pub date: NaiveDate,
+    /// Time ranges (first hour start, last hour end) rule is expected to match
+    pub ranges: Vec<(DateTime<FixedOffset>, DateTime<FixedOffset>)>,
+    pub max_speed: f32,
+}
+
+impl GoodWindForecast {
+    /// Finds good wind in hourly forecast ordered by time. Returns `None` if no good wind expected
+    ///
+    /// `date` and time ranges are in `timezone` of the station, whatever timezone the forecast was fetched in
+    /// (eg. UTC if the URL has no `timezone` parameter).
+    pub fn new<Tz: TimeZone>(
+        date: NaiveDate,
+        timezone: &Tz,
+        forecast: &[Observation],
+        rule: &Rule,
+    ) -> Option<Self> {
+        let forecast = forecast
+            .iter()
+            .map(|o| Observation {
+                time: local_time(&o.time, timezone),
+                ..o.clone()
+            })
+            .collect::<Vec<_>>();
+        let hours = forecast
+            .iter()
+            .filter(|o| o.time.date_naive() == date)
+            .filter(|o| rule.test(o))
+            .collect::<Vec<_>>();
+        let max_speed = hours.iter().map(|o| o.avg_speed).reduce(f32::max)?;
+        let ranges = time_ranges(&forecast, rule, Duration::hours(1))
+            .into_iter()
+            .filter(|(from, _)| from.date_naive() == date)
+            .map(|(from, to)| (from, to + Duration::hours(1)))
+            .collect();
+        Some(Self {
+            date,
+            ranges,
+            max_speed,
+        })
+    }
+
+    /// Formats notice (eg. `Good wind expected tomorrow 14:00–18:00 (up to 9.5 m/s)`)
+    pub fn format(&self, day: &str, unit: SpeedUnit) -> String {
+        let ranges = self
+            .ranges
+            .iter()
+            .map(|(from, to)| format!("{}–{}", from.format("%H:%M"), to.format("%H:%M")))
+            .collect::<Vec<_>>();
+        format!(
+            "Good wind expected {} {} (up to {})",
+            day,
+            ranges.join(", "),
+            unit.format(self.max_speed)
+        )
+    }
+}
+
+/// Time in `timezone` keeping the fixed offset representation
+fn local_time<Tz: TimeZone>(time: &DateTime<FixedOffset>, timezone: &Tz) -> DateTime<FixedOffset> {
+    let time = time.with_timezone(timezone);
+    time.with_timezone(&time.offset().fix())
+}
+
+/// Notices sent in the morning are about current day, notices sent after noon are about the next day
+pub fn notice_is_about_next_day(notice_at: NaiveTime) -> bool {
+    notice_at.hour() >= 12
+}
+
+/// Date forecast notice sent at a given time is about
+pub fn notice_date(today: NaiveDate, notice_at: NaiveTime) -> NaiveDate {
+    if notice_is_about_next_day(notice_at) {
+        today.succ()
+    } else {
+        today
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::Sector;
+
+    #[test]
+    fn parse_open_meteo_forecast() -> Result<()> {
+        let forecast = parse_forecast(include_str!("../tests/forecast.json"))?;
+        assert_eq!(47, forecast.len());
+
+        let first = &forecast[0];
+        assert_eq!(
+            DateTime::parse_from_rfc3339("2022-12-14T00:00:00+10:00")?,
+            first.time
+        );
+        assert_eq!(2.3, first.avg_speed);
+        assert_eq!(Some(5.1), first.gust_speed);
+        assert_eq!(Some(286), first.direction);
+        Ok(())
+    }
+
+    #[test]
+    fn forecast_speed_units() -> Result<()> {
+        let input = r#"{
+            "utc_offset_seconds": 0,
+            "hourly_units": {"windspeed_10m": "km/h"},
+            "hourly": {"time": ["2022-12-14T00:00"], "windspeed_10m": [36.0], "winddirection_10m": [90]}
+        }"#;
+        let forecast = parse_forecast(input)?;
+        assert_eq!(1, forecast.len());
+        assert!((forecast[0].avg_speed - 10.).abs() < 0.01);
+        assert_eq!(None, forecast[0].gust_speed);
+        Ok(())
+    }
+
+    #[test]
+    fn good_wind_forecast() -> Result<()> {
+        let forecast = parse_forecast(include_str!("../tests/forecast.json"))?;
+        let rule = Rule::speed_in_sector(7., Sector::NORTH_180);
+        let date = NaiveDate::from_ymd(2022, 12, 15);
+
+        let timezone = chrono_tz::Asia::Vladivostok;
+        let good_wind = GoodWindForecast::new(date, &timezone, &forecast, &rule).unwrap();
+        assert_eq!(
+            "Good wind expected tomorrow 10:00–14:00, 17:00–19:00 (up to 9.4 m/s)",
+            good_wind.format("tomorrow", SpeedUnit::MetersPerSecond)
+        );
+
+        // forecast fetched in UTC is about the same local day
+        let utc = forecast
+            .iter()
+            .map(|o| Observation {
+                time: o.time.with_timezone(&FixedOffset::east(0)),
+                ..o.clone()
+            })
+            .collect::<Vec<_>>();
+        let good_wind = GoodWindForecast::new(date, &timezone, &utc, &rule).unwrap();
+        assert_eq!(
+            "Good wind expected tomorrow 10:00–14:00, 17:00–19:00 (up to 9.4 m/s)",
+            good_wind.format("tomorrow", SpeedUnit::MetersPerSecond)
+        );
+
+        let date = NaiveDate::from_ymd(2022, 12, 14);
+        assert!(GoodWindForecast::new(date, &timezone, &forecast, &rule).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn forecast_notice_date() {
+        let today = NaiveDate::from_ymd(2022, 12, 14);
+        let tomorrow = NaiveDate::from_ymd(2022, 12, 15);
+        assert_eq!(today, notice_date(today, NaiveTime::from_hms(7, 0, 0)));
+        assert_eq!(tomorrow, notice_date(today, NaiveTime::from_hms(20, 0, 0)));
+    }
+}
@@ -0,0 +1,77 @@
+//! Liveness and readiness checks
+//!
+//! Bot is alive when the database is writable and the Telegram dispatcher is running. Restarting the bot
+//! can't fix a stale station, so fresh observations are only required for the bot to be ready.
+//!
+//! Probes don't touch the database: the observation loop reports the result of every save and the time of the last
+//! observation, so probes are answered instantly even while the database is busy (eg. with statistics).
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+
+/// Value of the last observation time before any observation is received
+const NO_OBSERVATION: i64 = i64::MIN;
+
+pub struct Health {
+    max_observation_age: Duration,
+    database_available: AtomicBool,
+    dispatcher_running: AtomicBool,
+    /// Unix time of the last observation
+    last_observation: AtomicI64,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub database: bool,
+    pub dispatcher: bool,
+    pub observations: bool,
+}
+
+impl Health {
+    /// Observations are fresh if the last one is not older than `max_observation_age`
+    pub fn new(max_observation_age: Duration) -> Self {
+        Self {
+            max_observation_age,
+            database_available: AtomicBool::new(true),
+            dispatcher_running: AtomicBool::new(false),
+            last_observation: AtomicI64::new(NO_OBSERVATION),
+        }
+    }
+
+    pub fn set_dispatcher_running(&self, running: bool) {
+        self.dispatcher_running.store(running, Ordering::Relaxed);
+    }
+
+    /// Records whether the last database write succeeded
+    pub fn set_database_available(&self, available: bool) {
+        self.database_available.store(available, Ordering::Relaxed);
+    }
+
+    /// Records time of a new observation
+    pub fn observed<Tz: TimeZone>(&self, time: &DateTime<Tz>) {
+        self.last_observation
+            .fetch_max(time.timestamp(), Ordering::Relaxed);
+    }
+
+    pub fn status(&self) -> Status {
+        let observations = match self.last_observation.load(Ordering::Relaxed) {
+            NO_OBSERVATION => false,
+            time => Utc::now().timestamp() - time <= self.max_observation_age.num_seconds(),
+        };
+        Status {
+            database: self.database_available.load(Ordering::Relaxed),
+            dispatcher: self.dispatcher_running.load(Ordering::Relaxed),
+            observations,
+        }
+    }
+}
+
+impl Status {
+    pub fn is_alive(&self) -> bool {
+        self.database && self.dispatcher
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.is_alive() && self.observations
+    }
+}
@@ -0,0 +1,265 @@
+//! Observations history
+//!
+//! Observations and forecasts are stored per source (spot name), so several stations can share the same database.
+//! Time is stored as a UTC timestamp along with the UTC offset of the station at that moment, so queries are not
+//! affected by timezone or DST of the station and observations are listed in station local time.
+//!
+//! Every fetched forecast is kept, so an hour can be listed as it was forecasted a given time in advance.
+use crate::{
+    checkin::{CheckIn, CheckIns},
+    models::{
+        Alert, ForecastRecord, NewAlert, NewCheckIn, NewForecast, NewObservation, NewSession,
+        ObservationRecord, SessionRecord,
+    },
+    parser::Observation,
+    prelude::*,
+    schema::{alerts, check_ins, forecasts, observations, sessions},
+    session::Session,
+};
+use anyhow::Context;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use diesel::{prelude::*, Connection, SqliteConnection};
+
+pub struct History(pub SqliteConnection);
+
+impl History {
+    pub fn new(database_url: &str) -> Result<Self> {
+        let connection = SqliteConnection::establish(database_url)
+            .context(OpeningSqliteDatabase(database_url.to_string()))?;
+        Self::with_connection(connection)
+    }
+
+    pub fn with_connection(connection: SqliteConnection) -> Result<Self> {
+        Ok(Self(connection))
+    }
+
+    /// Saves observation. Returns `false` if observation with the same time is already saved for a given source
+    pub fn save(&mut self, source: &str, observation: &Observation) -> Result<bool> {
+        let inserted = diesel::insert_or_ignore_into(observations::table)
+            .values(NewObservation::new(source, observation))
+            .execute(&mut self.0)
+            .context(SavingObservation(source.to_string()))?;
+        Ok(inserted > 0)
+    }
+
+    /// Saves observations in a single transaction skipping already saved ones. Returns number of saved observations
+    pub fn save_all(&mut self, source: &str, observations: &[Observation]) -> Result<usize> {
+        self.0
+            .transaction(|connection| {
+                let mut saved = 0;
+                for observation in observations {
+                    saved += diesel::insert_or_ignore_into(observations::table)
+                        .values(NewObservation::new(source, observation))
+                        .execute(connection)?;
+                }
+                diesel::QueryResult::Ok(saved)
+            })
+            .context(SavingObservation(source.to_string()))
+    }
+
+    /// Lists observations of a given source made in `[from, to)` interval ordered by time
+    pub fn list<Tz: TimeZone>(
+        &mut self,
+        source: &str,
+        from: &DateTime<Tz>,
+        to: &DateTime<Tz>,
+    ) -> Result<Vec<Observation>> {
+        use observations::dsl;
+        let records: Vec<ObservationRecord> = dsl::observations
+            .filter(dsl::source.eq(source))
+            .filter(dsl::time.ge(from.timestamp()))
+            .filter(dsl::time.lt(to.timestamp()))
+            .order(dsl::time.asc())
+            .load(&mut self.0)?;
+        records.into_iter().map(Observation::try_from).collect()
+    }
+
+    /// Sources having at least one observation
+    pub fn sources(&mut self) -> Result<Vec<String>> {
+        use observations::dsl;
+        Ok(dsl::observations
+            .select(dsl::source)
+            .distinct()
+            .order(dsl::source.asc())
+            .load(&mut self.0)?)
+    }
+
+    /// Most recent observation of a given source
+    pub fn latest(&mut self, source: &str) -> Result<Option<Observation>> {
+        use observations::dsl;
+        let record: Option<ObservationRecord> = dsl::observations
+            .filter(dsl::source.eq(source))
+            .order(dsl::time.desc())
+            .first(&mut self.0)
+            .optional()?;
+        record.map(Observation::try_from).transpose()
+    }
+
+    /// Saves forecasted hours. Previous forecasts for the same hours are kept
+    ///
+    /// Only hours after `fetched_at` are saved, so the forecast for past hours stays as it was before the fact.
+    pub fn save_forecast<Tz: TimeZone>(
+        &mut self,
+        source: &str,
+        fetched_at: &DateTime<Tz>,
+        forecast: &[Observation],
+    ) -> Result<usize> {
+        let records = forecast
+            .iter()
+            .filter(|f| f.time.timestamp() > fetched_at.timestamp())
+            .map(|f| NewForecast::new(source, fetched_at.timestamp(), f))
+            .collect::<Vec<_>>();
+        let mut saved = 0;
+        for record in records {
+            saved += diesel::replace_into(forecasts::table)
+                .values(record)
+                .execute(&mut self.0)
+                .context(SavingForecast(source.to_string()))?;
+        }
+        Ok(saved)
+    }
+
+    /// Lists the latest forecast of hours of a given source in `[from, to)` interval ordered by time
+    pub fn list_forecast<Tz: TimeZone>(
+        &mut self,
+        source: &str,
+        from: &DateTime<Tz>,
+        to: &DateTime<Tz>,
+    ) -> Result<Vec<Observation>> {
+        self.list_forecast_ahead(source, from, to, Duration::zero())
+    }
+
+    /// Lists forecasted hours of a given source in `[from, to)` interval ordered by time as they were forecasted
+    /// at least `lead` in advance. Hours not forecasted that early are skipped
+    pub fn list_forecast_ahead<Tz: TimeZone>(
+        &mut self,
+        source: &str,
+        from: &DateTime<Tz>,
+        to: &DateTime<Tz>,
+        lead: Duration,
+    ) -> Result<Vec<Observation>> {
+        use forecasts::dsl;
+        let mut records: Vec<ForecastRecord> = dsl::forecasts
+            .filter(dsl::source.eq(source))
+            .filter(dsl::time.ge(from.timestamp()))
+            .filter(dsl::time.lt(to.timestamp()))
+            .filter((dsl::time - dsl::fetched_at).ge(lead.num_seconds()))
+            .order((dsl::time.asc(), dsl::fetched_at.desc()))
+            .load(&mut self.0)?;
+        // the latest of suitable forecasts goes first
+        records.dedup_by_key(|r| r.time);
+        records.into_iter().map(Observation::try_from).collect()
+    }
+
+    /// Saves started session. Returns its id
+    pub fn save_session(&mut self, source: &str, session: &Session) -> Result<i32> {
+        use sessions::dsl;
+        let record = NewSession::new(source, session);
+        let id = self
+            .0
+            .transaction(|connection| {
+                diesel::replace_into(sessions::table)
+                    .values(&record)
+                    .execute(connection)?;
+                dsl::sessions
+                    .select(dsl::id)
+                    .filter(dsl::source.eq(source))
+                    .filter(dsl::started_at.eq(record.started_at))
+                    .first(connection)
+            })
+            .context(SavingSession(source.to_string()))?;
+        Ok(id)
+    }
+
+    /// Updates session in progress (or ended)
+    pub fn update_session(&mut self, id: i32, source: &str, session: &Session) -> Result<()> {
+        use sessions::dsl;
+        diesel::update(dsl::sessions.find(id))
+            .set(NewSession::new(source, session))
+            .execute(&mut self.0)
+            .context(SavingSession(source.to_string()))?;
+        Ok(())
+    }
+
+    /// Saves check-in of a user replacing the previous one of the session
+    pub fn check_in(
+        &mut self,
+        session_id: i32,
+        user_id: i64,
+        name: &str,
+        check_in: CheckIn,
+    ) -> Result<()> {
+        diesel::replace_into(check_ins::table)
+            .values(NewCheckIn {
+                session_id,
+                user_id,
+                name,
+                status: check_in.as_str(),
+                updated_at: Utc::now().timestamp(),
+            })
+            .execute(&mut self.0)
+            .context(SavingCheckIn(user_id))?;
+        Ok(())
+    }
+
+    /// Check-ins of a session in order they were made
+    pub fn check_ins(&mut self, session_id: i32) -> Result<CheckIns> {
+        use check_ins::dsl;
+        let records: Vec<(String, String)> = dsl::check_ins
+            .select((dsl::name, dsl::status))
+            .filter(dsl::session_id.eq(session_id))
+            .order((dsl::updated_at.asc(), dsl::id.asc()))
+            .load(&mut self.0)?;
+        let check_ins = records
+            .into_iter()
+            .map(|(name, status)| Ok((name, status.parse()?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(CheckIns::new(check_ins))
+    }
+
+    /// Saves alert message sent during a session
+    pub fn save_alert(
+        &mut self,
+        session_id: i32,
+        chat_id: i64,
+        message_id: i32,
+        text: &str,
+    ) -> Result<()> {
+        diesel::insert_into(alerts::table)
+            .values(NewAlert {
+                session_id,
+                chat_id,
+                message_id,
+                text,
+            })
+            .execute(&mut self.0)?;
+        Ok(())
+    }
+
+    /// Alert messages sent during a session
+    pub fn alerts(&mut self, session_id: i32) -> Result<Vec<Alert>> {
+        use alerts::dsl;
+        let alerts = dsl::alerts
+            .filter(dsl::session_id.eq(session_id))
+            .order(dsl::id.asc())
+            .load(&mut self.0)?;
+        Ok(alerts)
+    }
+
+    /// Lists sessions of a given source started in `[from, to)` interval ordered by start time
+    pub fn list_sessions<Tz: TimeZone>(
+        &mut self,
+        source: &str,
+        from: &DateTime<Tz>,
+        to: &DateTime<Tz>,
+    ) -> Result<Vec<Session>> {
+        use sessions::dsl;
+        let records: Vec<SessionRecord> = dsl::sessions
+            .filter(dsl::source.eq(source))
+            .filter(dsl::started_at.ge(from.timestamp()))
+            .filter(dsl::started_at.lt(to.timestamp()))
+            .order(dsl::started_at.asc())
+            .load(&mut self.0)?;
+        records.into_iter().map(Session::try_from).collect()
+    }
+}
@@ -0,0 +1,137 @@
+//! Observations from an HTML table of arbitrary layout
+//!
+//! Many anemometer sites publish observations as similar HTML tables differing only in column order and formats,
+//! so a table is described by the CSS selector of its rows and the columns observation fields are read from.
+use crate::{
+    parser::{parse_rows, Columns, ParseError, Parsed, StationClock},
+    units::SpeedUnit,
+};
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct HtmlTable {
+    /// CSS selector of table rows (eg. `table#wind tr`)
+    #[serde(default = "default_selector")]
+    pub selector: String,
+    /// Columns are given by index or header text. Header is read from the first row having `th` cells, or from
+    /// the first row if there is none
+    #[serde(default)]
+    pub columns: Columns,
+}
+
+fn default_selector() -> String {
+    "table tr".to_string()
+}
+
+impl Default for HtmlTable {
+    fn default() -> Self {
+        Self {
+            selector: default_selector(),
+            columns: Columns::default(),
+        }
+    }
+}
+
+impl HtmlTable {
+    /// Parses table rows. Speeds in the table are given in `unit`
+    pub fn parse(
+        &self,
+        input: &str,
+        clock: &StationClock,
+        unit: SpeedUnit,
+    ) -> Result<Parsed, ParseError> {
+        let rows = Selector::parse(&self.selector)
+            .map_err(|_| ParseError::InvalidSelector(self.selector.clone()))?;
+        let header_cell = Selector::parse("th").unwrap();
+        let cell = Selector::parse("td, th").unwrap();
+        let texts = |row: ElementRef| {
+            row.select(&cell)
+                .map(|c| c.text().collect::<String>())
+                .collect::<Vec<_>>()
+        };
+
+        let document = Html::parse_document(input);
+        let mut header = None;
+        let mut data = vec![];
+        for row in document.select(&rows) {
+            if row.select(&header_cell).next().is_some() {
+                header.get_or_insert_with(|| texts(row));
+            } else {
+                data.push(texts(row));
+            }
+        }
+        if header.is_none() && self.columns.needs_header() && !data.is_empty() {
+            header = Some(data.remove(0));
+        }
+
+        let layout = self.columns.resolve(&header.unwrap_or_default())?;
+        Ok(parse_rows(data, &layout, clock, unit))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::parser::ColumnRef;
+
+    const TABLE: &str = r#"<html><body>
+        <table class="legend"><tr><td>not observations</td></tr></table>
+        <table id="wind">
+            <tr><th>Скорость, уз</th><th>Порывы, уз</th><th>Направление</th><th>Дата</th></tr>
+            <tr><td>10</td><td>15</td><td>СЗ (315°)</td><td>2022-10-29 22:46</td></tr>
+            <tr><td>12</td><td></td><td>З (270°)</td><td>2022-10-29 22:36</td></tr>
+        </table>
+    </body></html>"#;
+
+    #[test]
+    fn table_with_custom_layout() {
+        let table = HtmlTable {
+            selector: "table#wind tr".to_string(),
+            columns: Columns {
+                time: ColumnRef::Header("дата".to_string()),
+                direction: ColumnRef::Index(2),
+                avg_speed: ColumnRef::Header("Скорость, уз".to_string()),
+                gust_speed: Some(ColumnRef::Index(1)),
+            },
+        };
+        let clock = StationClock {
+            date_format: "%Y-%m-%d %H:%M".to_string(),
+            ..StationClock::default()
+        };
+        let parsed = table.parse(TABLE, &clock, SpeedUnit::Knots).unwrap();
+        assert!(parsed.errors.is_empty());
+
+        let observations = parsed
+            .observations
+            .iter()
+            .map(|o| o.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["22:46 5.1 m/s NW ↘ (315°)", "22:36 6.2 m/s W  → (270°)"],
+            observations
+        );
+        assert_eq!(
+            Some(SpeedUnit::Knots.to_ms(15.)),
+            parsed.observations[0].gust_speed
+        );
+        assert_eq!(None, parsed.observations[1].gust_speed);
+    }
+
+    #[test]
+    fn unknown_header() {
+        let table = HtmlTable {
+            columns: Columns {
+                time: ColumnRef::Header("Время".to_string()),
+                ..Columns::default()
+            },
+            ..HtmlTable::default()
+        };
+        let result = table.parse(TABLE, &StationClock::default(), SpeedUnit::MetersPerSecond);
+        assert_eq!(
+            Some(ParseError::UnknownHeader("Время".to_string())),
+            result.err()
+        );
+    }
+}
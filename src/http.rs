@@ -0,0 +1,61 @@
+//! HTTP server exposing bot internals (Prometheus metrics, health checks), live feed and optional REST API
+use crate::{
+    api::{self, Api},
+    health::{Health, Status},
+    live::{self, LiveFeed},
+    metrics,
+    prelude::*,
+    supervisor::Shutdown,
+};
+use anyhow::Context;
+use axum::{http::StatusCode, routing::get, Extension, Json, Router};
+use std::{net::SocketAddr, sync::Arc};
+
+/// Service endpoints router. REST API is served under `/api` if given
+pub fn router(health: Arc<Health>, feed: Arc<LiveFeed>, api: Option<Arc<Api>>) -> Router {
+    let router = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(liveness_handler))
+        .route("/readyz", get(readiness_handler))
+        .layer(Extension(health))
+        .merge(live::router(feed));
+    match api {
+        Some(api) => router.nest("/api", api::router(api)),
+        None => router,
+    }
+}
+
+/// Serves given router until shutdown. Requests in progress are completed before returning
+pub async fn serve(addr: SocketAddr, router: Router, mut shutdown: Shutdown) -> Result<()> {
+    info!("Listening HTTP on {}", addr);
+    axum::Server::try_bind(&addr)
+        .context(BindingHttpServer(addr))?
+        .serve(router.into_make_service())
+        .with_graceful_shutdown(async move { shutdown.wait().await })
+        .await?;
+    Ok(())
+}
+
+async fn metrics_handler() -> String {
+    metrics::render()
+}
+
+async fn liveness_handler(Extension(health): Extension<Arc<Health>>) -> (StatusCode, Json<Status>) {
+    let status = health.status();
+    (status_code(status.is_alive()), Json(status))
+}
+
+async fn readiness_handler(
+    Extension(health): Extension<Arc<Health>>,
+) -> (StatusCode, Json<Status>) {
+    let status = health.status();
+    (status_code(status.is_ready()), Json(status))
+}
+
+fn status_code(ok: bool) -> StatusCode {
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
@@ -0,0 +1,140 @@
+//! Observations from JSON payloads (eg. Holfuy or WindGuru-like APIs)
+//!
+//! Fields are located with dotted paths (eg. `wind.speed` or `data.0.time`), numeric segments index arrays.
+use crate::{
+    parser::{parse_rows, Columns, ParseError, Parsed, StationClock},
+    units::SpeedUnit,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct JsonFeed {
+    /// Path to the array of observations. Whole payload is a single observation if not given
+    #[serde(default)]
+    pub items: Option<String>,
+    /// Paths to observation fields relative to an item
+    pub fields: Fields,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Fields {
+    pub time: String,
+    pub direction: String,
+    pub avg_speed: String,
+    #[serde(default)]
+    pub gust_speed: Option<String>,
+}
+
+impl JsonFeed {
+    /// Parses observations. Speeds in the payload are given in `unit`
+    pub fn parse(
+        &self,
+        input: &str,
+        clock: &StationClock,
+        unit: SpeedUnit,
+    ) -> Result<Parsed, ParseError> {
+        let payload: Value =
+            serde_json::from_str(input).map_err(|e| ParseError::Malformed(e.to_string()))?;
+        let items = match &self.items {
+            Some(path) => match lookup(&payload, path) {
+                Some(Value::Array(items)) => items.iter().collect(),
+                _ => return Err(ParseError::Malformed(format!("No array at '{}'", path))),
+            },
+            None => vec![&payload],
+        };
+
+        // items are converted to rows of the default table layout, so values are validated the same way
+        let fields = [
+            Some(&self.fields.time),
+            Some(&self.fields.direction),
+            Some(&self.fields.avg_speed),
+            self.fields.gust_speed.as_ref(),
+        ];
+        let rows = items.into_iter().map(|item| {
+            fields
+                .iter()
+                .map(|path| {
+                    let value = path.and_then(|path| lookup(item, path));
+                    value.map(to_text).unwrap_or_default()
+                })
+                .collect::<Vec<_>>()
+        });
+        let layout = Columns::default().resolve(&[])?;
+        Ok(parse_rows(rows, &layout, clock, unit))
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|segment| !segment.is_empty())
+        .try_fold(value, |value, segment| match value {
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+            value => value.get(segment),
+        })
+}
+
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use insta::assert_yaml_snapshot;
+
+    #[test]
+    fn json_feed() {
+        let input = include_str!("../tests/example.json");
+        let feed = JsonFeed {
+            items: Some("measurements".to_string()),
+            fields: Fields {
+                time: "dateTime".to_string(),
+                direction: "wind.direction".to_string(),
+                avg_speed: "wind.speed".to_string(),
+                gust_speed: Some("wind.gust".to_string()),
+            },
+        };
+        let clock = StationClock {
+            date_format: "%Y-%m-%d %H:%M:%S".to_string(),
+            ..StationClock::default()
+        };
+        let parsed = feed.parse(input, &clock, SpeedUnit::Knots).unwrap();
+        assert_eq!(
+            vec!["Row 3: invalid average speed ''"],
+            parsed
+                .errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        );
+        assert_yaml_snapshot!(parsed.observations);
+    }
+
+    #[test]
+    fn single_observation_payload() {
+        let input = r#"{"time": 1667047560, "dir": "calm", "speed": 0}"#;
+        let feed = JsonFeed {
+            items: None,
+            fields: Fields {
+                time: "time".to_string(),
+                direction: "dir".to_string(),
+                avg_speed: "speed".to_string(),
+                gust_speed: None,
+            },
+        };
+        let clock = StationClock {
+            date_format: "%s".to_string(),
+            ..StationClock::default()
+        };
+        let parsed = feed
+            .parse(input, &clock, SpeedUnit::MetersPerSecond)
+            .unwrap();
+        assert_eq!("22:46 0.0 m/s calm", parsed.observations[0].to_string());
+    }
+}
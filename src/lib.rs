@@ -1,17 +1,55 @@
+pub mod accuracy;
+pub mod api;
+pub mod chart;
+pub mod checkin;
+pub mod csv_log;
+pub mod digest;
+pub mod export;
+pub mod fetch;
+pub mod forecast;
+pub mod health;
+pub mod history;
+pub mod html;
+pub mod http;
+pub mod json_feed;
+pub mod live;
+pub mod metrics;
 mod models;
 pub mod parser;
+pub mod polling;
+pub mod rule;
 mod schema;
+mod sector;
+pub mod session;
+pub mod source;
+pub mod stats;
+pub mod supervisor;
+pub mod units;
+pub mod webhook;
 
 use anyhow::Context;
+use chrono::NaiveTime;
 use diesel::prelude::*;
 use diesel::{Connection, SqliteConnection};
-use models::{NewSubscription, Subscription};
+use models::NewSubscription;
+pub use models::{Alert, Subscription};
 use parser::Observation;
 use prelude::*;
+use rule::Rule;
 use schema::subscriptions;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub use sector::{compass_point_index, compass_point_name, CircleArc, Sector};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use units::SpeedUnit;
+
+pub type Shared<T> = Arc<Mutex<T>>;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(tag = "state", content = "steps", rename_all = "snake_case")]
 pub enum WindState {
     Low,
     Candidate(u8),
@@ -38,6 +76,36 @@ pub mod prelude {
 
         #[error("Removing subscription for user {0}")]
         RemovingSubscription(i64),
+
+        #[error("Saving rule for user {0}")]
+        SavingRule(i64),
+
+        #[error("Saving settings for user {0}")]
+        SavingSettings(i64),
+
+        #[error("Saving observation from {0}")]
+        SavingObservation(String),
+
+        #[error("Failed to get forecast from endpoint: {0}")]
+        ForecastEndpointFailed(String),
+
+        #[error("Saving forecast for {0}")]
+        SavingForecast(String),
+
+        #[error("Saving session at {0}")]
+        SavingSession(String),
+
+        #[error("Saving check-in of user {0}")]
+        SavingCheckIn(i64),
+
+        #[error("Binding HTTP server to {0}")]
+        BindingHttpServer(std::net::SocketAddr),
+
+        #[error("Reading sources config: {0}")]
+        ReadingSources(String),
+
+        #[error("Reading observations from {0}")]
+        ReadingFile(String),
     }
 }
 
@@ -48,33 +116,54 @@ pub mod prelude {
 /// created just for that reason.
 pub struct WindTracker {
     pub state: WindState,
-    pub wind_sector: Sector,
+    /// Rule each observation should match to be counted towards [`WindState::High`]
+    pub rule: Rule,
     /// Number of steps require for FSM to reach [`WindState::High`] from [`WindState::Low`]
     pub candidate_steps: u8,
     /// Number of steps require for FSM to reset from [`WindState::High`] to [`WindState::Low`]
     pub cooldown_steps: u8,
-    /// Target threshold for wind speed
-    pub avg_speed_threshold: f32,
+    /// Maximum circular standard deviation of wind direction (in degrees) over the candidate window.
+    ///
+    /// FSM will not reach [`WindState::High`] until wind direction is steady enough. `None` disables the check.
+    pub max_direction_spread: Option<f32>,
+    /// Directions of the last matching observations (candidate window). `None` for calm or variable wind
+    directions: VecDeque<Option<u16>>,
 }
 
 impl WindTracker {
+    pub fn new(rule: Rule, candidate_steps: u8, cooldown_steps: u8) -> Self {
+        Self {
+            state: WindState::Low,
+            rule,
+            candidate_steps,
+            cooldown_steps,
+            max_direction_spread: None,
+            directions: VecDeque::new(),
+        }
+    }
+
     /// Returns true if target event is found (FSM reach [`WindState::High`] state)
     pub fn step(&mut self, observation: &Observation) -> bool {
         use WindState::*;
 
         let before_state = self.state;
-        let direction_match = self.wind_sector.test(observation.direction);
-        let speed_match = observation.avg_speed >= self.avg_speed_threshold;
-        self.state = if speed_match && direction_match {
+        self.state = if self.rule.test(observation) {
+            self.directions.push_back(observation.direction);
+            while self.directions.len() > self.candidate_steps as usize + 1 {
+                self.directions.pop_front();
+            }
+            let stable = self.is_direction_stable();
             match self.state {
                 High => High,
                 Low if self.candidate_steps == 0 => High,
                 Low => Candidate(1),
-                Candidate(i) if i >= self.candidate_steps => High,
+                Candidate(i) if i >= self.candidate_steps && stable => High,
+                Candidate(i) if i >= self.candidate_steps => Candidate(i),
                 Candidate(i) => Candidate(i + 1),
                 Cooldown(..) => High,
             }
         } else {
+            self.directions.clear();
             match self.state {
                 Low => Low,
                 Candidate(..) => Low,
@@ -93,48 +182,39 @@ impl WindTracker {
     pub fn state(&self) -> WindState {
         self.state
     }
+
+    fn is_direction_stable(&self) -> bool {
+        match self.max_direction_spread {
+            // variable direction is never steady
+            Some(max_spread) => match self.directions.iter().copied().collect::<Option<Vec<_>>>() {
+                Some(directions) => direction_spread(directions) <= max_spread,
+                None => false,
+            },
+            None => true,
+        }
+    }
 }
 
-/// Circle sector
+/// Circular standard deviation of given directions (in degrees)
 ///
-/// Can test if given angle (0-359 deg.) is in circle sector.
-/// Sector is defined as two angles (from angle and to angle). Two angles
-/// always given in clockwise order, so `Sector::new(270, 90)` is upper half circle and
-/// `Sector::new(90, 270)` is lower.
-pub struct Sector(u16, u16);
-
-impl Sector {
-    #[allow(dead_code)]
-    pub const NORTH_180: Sector = Sector(270, 90);
-
-    #[allow(dead_code)]
-    pub const SOUTH_180: Sector = Sector(90, 270);
-
-    #[allow(dead_code)]
-    pub const EAST_180: Sector = Sector(0, 180);
-
-    #[allow(dead_code)]
-    pub const WEST_180: Sector = Sector(180, 0);
-
-    #[allow(dead_code)]
-    pub const NORTH_90: Sector = Sector(315, 45);
-
-    #[allow(dead_code)]
-    pub const EAST_90: Sector = Sector(45, 135);
-
-    #[allow(dead_code)]
-    pub const SOUTH_90: Sector = Sector(135, 225);
-
-    #[allow(dead_code)]
-    pub const WEST_90: Sector = Sector(225, 315);
-
-    fn test(&self, angle: u16) -> bool {
-        let angle = angle % 360;
-        if self.0 <= self.1 {
-            self.0 <= angle && angle <= self.1
-        } else {
-            self.0 <= angle || angle <= self.1
-        }
+/// Directions are treated as angles, so 350° and 10° are 20° apart, not 340°.
+/// Returns `0` for an empty input and `f32::INFINITY` if directions cancel each other out completely.
+pub fn direction_spread(directions: impl IntoIterator<Item = u16>) -> f32 {
+    let (mut sin, mut cos, mut n) = (0f32, 0f32, 0usize);
+    for direction in directions {
+        let angle = (direction as f32).to_radians();
+        sin += angle.sin();
+        cos += angle.cos();
+        n += 1;
+    }
+    if n == 0 {
+        return 0.;
+    }
+    let r = (sin / n as f32).hypot(cos / n as f32);
+    if r >= 1. {
+        0.
+    } else {
+        (-2. * r.ln()).sqrt().to_degrees()
     }
 }
 
@@ -170,6 +250,89 @@ impl Subscriptions {
         Ok(subscriptions.load(&mut self.0)?)
     }
 
+    pub fn find_subscription(&mut self, user_id: i64) -> Result<Option<Subscription>> {
+        use schema::subscriptions::dsl::{subscriptions, user_id as subsciption_user_id};
+        Ok(subscriptions
+            .filter(subsciption_user_id.eq(user_id))
+            .first(&mut self.0)
+            .optional()?)
+    }
+
+    /// Sets custom alert rule for a user. `None` resets user to the default rule
+    ///
+    /// Returns `false` if user is not subscribed
+    pub fn set_rule(&mut self, user_id: i64, rule: Option<&Rule>) -> Result<bool> {
+        use schema::subscriptions::dsl::{
+            rule as subscription_rule, subscriptions, user_id as subsciption_user_id,
+        };
+        let updated = diesel::update(subscriptions)
+            .filter(subsciption_user_id.eq(user_id))
+            .set(subscription_rule.eq(rule.map(Rule::to_string)))
+            .execute(&mut self.0)
+            .context(SavingRule(user_id))?;
+        Ok(updated > 0)
+    }
+
+    /// Sets alert speed threshold (m/s) for a user. `None` resets user to the default threshold
+    ///
+    /// Returns `false` if user is not subscribed
+    pub fn set_threshold(&mut self, user_id: i64, threshold: Option<f32>) -> Result<bool> {
+        use schema::subscriptions::dsl::{
+            subscriptions, threshold as subscription_threshold, user_id as subsciption_user_id,
+        };
+        let updated = diesel::update(subscriptions)
+            .filter(subsciption_user_id.eq(user_id))
+            .set(subscription_threshold.eq(threshold))
+            .execute(&mut self.0)
+            .context(SavingSettings(user_id))?;
+        Ok(updated > 0)
+    }
+
+    /// Sets speed unit used to display observations to a user
+    ///
+    /// Returns `false` if user is not subscribed
+    pub fn set_speed_unit(&mut self, user_id: i64, unit: SpeedUnit) -> Result<bool> {
+        use schema::subscriptions::dsl::{
+            speed_unit, subscriptions, user_id as subsciption_user_id,
+        };
+        let updated = diesel::update(subscriptions)
+            .filter(subsciption_user_id.eq(user_id))
+            .set(speed_unit.eq(unit.to_string()))
+            .execute(&mut self.0)
+            .context(SavingSettings(user_id))?;
+        Ok(updated > 0)
+    }
+
+    /// Sets station local time daily digest is sent at. `None` disables digest
+    ///
+    /// Returns `false` if user is not subscribed
+    pub fn set_digest_time(&mut self, user_id: i64, time: Option<NaiveTime>) -> Result<bool> {
+        use schema::subscriptions::dsl::{
+            digest_at, subscriptions, user_id as subsciption_user_id,
+        };
+        let updated = diesel::update(subscriptions)
+            .filter(subsciption_user_id.eq(user_id))
+            .set(digest_at.eq(time.map(|t| t.format("%H:%M").to_string())))
+            .execute(&mut self.0)
+            .context(SavingSettings(user_id))?;
+        Ok(updated > 0)
+    }
+
+    /// Sets station local time good wind forecast notice is sent at. `None` disables notices
+    ///
+    /// Returns `false` if user is not subscribed
+    pub fn set_forecast_time(&mut self, user_id: i64, time: Option<NaiveTime>) -> Result<bool> {
+        use schema::subscriptions::dsl::{
+            forecast_at, subscriptions, user_id as subsciption_user_id,
+        };
+        let updated = diesel::update(subscriptions)
+            .filter(subsciption_user_id.eq(user_id))
+            .set(forecast_at.eq(time.map(|t| t.format("%H:%M").to_string())))
+            .execute(&mut self.0)
+            .context(SavingSettings(user_id))?;
+        Ok(updated > 0)
+    }
+
     pub fn remove_subscription(&mut self, user_id: i64) -> Result<()> {
         use schema::subscriptions::dsl::{subscriptions, user_id as subsciption_user_id};
         diesel::delete(subscriptions)
@@ -193,17 +356,18 @@ mod test {
 
     impl ObservationSequence {
         fn next(&mut self, avg_speed: f32, direction: u16) -> Observation {
-            self.time = self.time + Duration::minutes(1);
+            self.time += Duration::minutes(1);
             Observation {
                 time: self.time,
                 avg_speed,
-                direction,
+                gust_speed: None,
+                direction: Some(direction),
             }
         }
     }
 
     fn step(fsm: &mut WindTracker, observation: &Observation) -> WindState {
-        fsm.step(&observation);
+        fsm.step(observation);
         fsm.state()
     }
 
@@ -215,13 +379,8 @@ mod test {
             time: DateTime::parse_from_rfc3339("2022-02-01T00:00:00+10:00").unwrap(),
         };
 
-        let fsm = WindTracker {
-            state: WindState::Low,
-            wind_sector: Sector(135, 225), // SE-SW
-            candidate_steps,
-            cooldown_steps,
-            avg_speed_threshold: 5.0,
-        };
+        let rule = Rule::speed_in_sector(5.0, Sector::new(135, 225)); // SE-SW
+        let fsm = WindTracker::new(rule, candidate_steps, cooldown_steps);
         (seq, fsm)
     }
 
@@ -264,24 +423,23 @@ mod test {
     }
 
     #[test]
-    fn sector() {
-        let sector = Sector(0, 45);
-
-        assert_eq!(true, sector.test(0));
-        assert_eq!(true, sector.test(30));
-        assert_eq!(true, sector.test(45));
-
-        assert_eq!(false, sector.test(46));
-        assert_eq!(false, sector.test(359));
-
-        let sector = Sector(280, 90);
+    fn fsm_unstable_direction() {
+        let (mut seq, mut fsm) = new_seq_and_fsm(2, 2);
+        fsm.max_direction_spread = Some(15.);
 
-        assert_eq!(true, sector.test(290));
-        assert_eq!(true, sector.test(0));
-        assert_eq!(true, sector.test(45));
-        assert_eq!(true, sector.test(90));
+        assert_eq!(step(&mut fsm, &seq.next(5.7, 140)), WindState::Candidate(1));
+        assert_eq!(step(&mut fsm, &seq.next(5.7, 220)), WindState::Candidate(2));
+        assert_eq!(step(&mut fsm, &seq.next(5.7, 150)), WindState::Candidate(2));
+        assert_eq!(step(&mut fsm, &seq.next(5.7, 160)), WindState::Candidate(2));
+        assert_eq!(step(&mut fsm, &seq.next(5.7, 155)), WindState::High);
+    }
 
-        assert_eq!(false, sector.test(180));
-        assert_eq!(false, sector.test(279));
+    #[test]
+    fn direction_spread_across_north() {
+        assert_eq!(0., direction_spread([]));
+        assert!(direction_spread([180, 180, 180]) < 0.1);
+        assert!(direction_spread([350, 10, 355, 5]) < 10.);
+        assert!(direction_spread([170, 190, 175, 185]) < 10.);
+        assert!(direction_spread([0, 90, 180, 270]) > 180.);
     }
 }
@@ -0,0 +1,175 @@
+//! Live feed of observations and wind state transitions
+//!
+//! Events are published by the parse loop and streamed to browser clients as Server-Sent Events at `GET /live`.
+//! Feed is public and read-only, so only spot-level state is published (not per-subscriber trackers).
+use crate::{parser::Observation, WindState};
+use axum::{
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Extension, Router,
+};
+use futures::{stream, Stream};
+use serde::Serialize;
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Number of events buffered for slow clients. Clients lagging behind skip missed events
+const CAPACITY: usize = 64;
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    Observation {
+        spot: String,
+        #[serde(flatten)]
+        observation: Observation,
+    },
+    WindState {
+        spot: String,
+        from: WindState,
+        to: WindState,
+    },
+}
+
+impl LiveEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::Observation { .. } => "observation",
+            LiveEvent::WindState { .. } => "wind_state",
+        }
+    }
+}
+
+pub struct LiveFeed {
+    /// `None` after the feed is closed
+    sender: Mutex<Option<broadcast::Sender<LiveEvent>>>,
+    /// Sent to clients right after connecting, so they don't wait for the next observation
+    last_observation: Mutex<Option<LiveEvent>>,
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        Self {
+            sender: Mutex::new(Some(broadcast::channel(CAPACITY).0)),
+            last_observation: Mutex::new(None),
+        }
+    }
+}
+
+impl LiveFeed {
+    pub fn publish(&self, event: LiveEvent) {
+        if let LiveEvent::Observation { .. } = event {
+            *self.last_observation.lock().unwrap() = Some(event.clone());
+        }
+        if let Some(sender) = &*self.sender.lock().unwrap() {
+            // error only means there are no connected clients
+            let _ = sender.send(event);
+        }
+    }
+
+    /// Ends streams of all connected clients, so HTTP server can shut down gracefully
+    pub fn close(&self) {
+        self.sender.lock().unwrap().take();
+    }
+
+    /// Stream of events starting with the last observation
+    pub fn subscribe(&self) -> impl Stream<Item = LiveEvent> {
+        let (last, receiver) = match &*self.sender.lock().unwrap() {
+            Some(sender) => (
+                self.last_observation.lock().unwrap().clone(),
+                sender.subscribe(),
+            ),
+            // receiver of a dropped sender is closed immediately
+            None => (None, broadcast::channel(1).1),
+        };
+        stream::unfold((last, receiver), |(last, mut receiver)| async move {
+            if let Some(event) = last {
+                return Some((event, (None, receiver)));
+            }
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, (None, receiver))),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
+
+pub fn router(feed: Arc<LiveFeed>) -> Router {
+    Router::new()
+        .route("/live", get(live_handler))
+        .layer(Extension(feed))
+}
+
+async fn live_handler(
+    Extension(feed): Extension<Arc<LiveFeed>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    use futures::StreamExt;
+    let events = feed.subscribe().map(|event| {
+        let sse = Event::default().event(event.name());
+        // serializing plain data structures can't fail
+        Ok(sse.json_data(event).unwrap())
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use chrono::DateTime;
+    use futures::StreamExt;
+
+    fn observation() -> LiveEvent {
+        LiveEvent::Observation {
+            spot: "rvs".to_string(),
+            observation: Observation {
+                time: DateTime::parse_from_rfc3339("2022-10-29T22:46:00+10:00").unwrap(),
+                direction: Some(315),
+                avg_speed: 5.,
+                gust_speed: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribers_get_last_observation_first() {
+        let feed = LiveFeed::default();
+        feed.publish(observation());
+
+        let mut events = Box::pin(feed.subscribe());
+        feed.publish(LiveEvent::WindState {
+            spot: "rvs".to_string(),
+            from: WindState::Low,
+            to: WindState::Candidate(1),
+        });
+
+        assert_eq!("observation", events.next().await.unwrap().name());
+        assert_eq!("wind_state", events.next().await.unwrap().name());
+
+        feed.close();
+        assert!(events.next().await.is_none());
+        assert!(Box::pin(feed.subscribe()).next().await.is_none());
+    }
+
+    #[test]
+    fn event_serialization() {
+        let json = serde_json::to_value(observation()).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "type": "observation",
+                "spot": "rvs",
+                "time": "2022-10-29T22:46:00+10:00",
+                "direction": 315,
+                "avg_speed": 5.0,
+                "gust_speed": null
+            }),
+            json
+        );
+    }
+}
@@ -1,27 +1,62 @@
-use anyhow::Context;
-use chrono::{DateTime, FixedOffset};
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use clap::{Parser, Subcommand};
 use dotenv::dotenv;
-use futures::{stream, Stream, StreamExt};
-use parser::{parse, Observation};
+use futures::{future, stream, Stream, StreamExt};
+use parser::{Observation, StationClock};
+use reqwest::{Client, Url};
 use std::{
     cmp::Reverse,
+    collections::HashMap,
+    convert::Infallible,
     env,
+    fs::{self, File},
+    future::Future,
+    io::{self, BufWriter},
+    net::SocketAddr,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
+};
+use telewind::{
+    accuracy::{self, Accuracy},
+    api::Api,
+    csv_log::CsvLog,
+    digest::{digest_date, summarizes_previous_day, DailySummary},
+    export::{export, resample, ExportFormat},
+    fetch::{http_client, Fetcher, USER_AGENT},
+    forecast::{fetch_forecast, notice_date, notice_is_about_next_day, GoodWindForecast},
+    health::Health,
+    history::History,
+    http,
+    live::{LiveEvent, LiveFeed},
+    metrics,
+    parser::{self, STATION_DATE_FORMAT},
+    polling::Polling,
+    prelude::*,
+    rule::Rule,
+    session::{format_sessions, SessionEvent, SessionTracker},
+    source::{load_sources, Format, Source},
+    stats::{speed_bin_labels, Statistics},
+    supervisor::{supervise, Backoff, Shutdown},
+    units::{parse_speed, SpeedUnit},
+    webhook::Webhook,
+    Sector, Shared, Subscription, WindState, WindTracker,
 };
-use telewind::{parser, prelude::*, Sector, WindState, WindTracker};
 use teloxide::{
-    dispatching::UpdateFilterExt,
+    dispatching::{update_listeners::UpdateListener, DefaultKey, UpdateFilterExt},
     dptree::{self, deps},
+    error_handlers::LoggingErrorHandler,
     prelude::Dispatcher,
     requests::Requester,
     types::{ChatId, ChatKind, MediaKind, Message, MessageKind, Update},
-    Bot,
+    Bot, RequestError,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    time::{self, MissedTickBehavior},
 };
-use tokio::time::{self, Interval, MissedTickBehavior};
-
-type Shared<T> = Arc<Mutex<T>>;
 
 #[derive(Parser)]
 struct Args {
@@ -29,6 +64,120 @@ struct Args {
     action: Action,
 }
 
+#[derive(Parser, Debug, Clone)]
+struct BotArgs {
+    #[command(flatten)]
+    opts: Opts,
+
+    /// address of HTTP server exposing Prometheus `/metrics`, `/healthz`, `/readyz` and `/live` feed (eg. `0.0.0.0:9090`)
+    #[arg(long)]
+    http_listen: Option<SocketAddr>,
+
+    /// enables REST API under `/api` of HTTP server. Clients should provide `Authorization: Bearer <token>` header
+    #[arg(long)]
+    api_token: Option<String>,
+
+    /// maximum age (minutes) of the last observation for the bot to be ready
+    #[arg(long, default_value_t = 30)]
+    max_observation_age: i64,
+
+    /// public URL Telegram should POST updates to (eg. `https://example.com/telegram`). Enables webhook mode
+    /// instead of long polling. Secret token is read from `TELEGRAM_WEBHOOK_SECRET` environment variable (required
+    /// unless `--webhook-local` is given)
+    #[arg(long)]
+    webhook_url: Option<Url>,
+
+    /// address webhook HTTP server is listening on
+    #[arg(long, default_value = "0.0.0.0:8443")]
+    webhook_listen: SocketAddr,
+
+    /// don't register webhook with Telegram (for local testing with simulated update POSTs)
+    #[arg(long)]
+    webhook_local: bool,
+
+    /// polling interval (seconds) of the observation source until its reporting cadence is known
+    #[arg(long, default_value_t = 55)]
+    poll_interval: u64,
+
+    /// maximum polling interval (seconds) at night and after repeated failures
+    #[arg(long, default_value_t = 900)]
+    max_poll_interval: u64,
+
+    /// number of days `/accuracy` is calculated and `/sessions` are listed for by default
+    #[arg(long, default_value_t = 30)]
+    days: i64,
+}
+
+#[derive(Parser, Debug)]
+struct AccuracyArgs {
+    #[command(flatten)]
+    opts: Opts,
+
+    /// number of days forecast accuracy is calculated for
+    #[arg(long, default_value_t = 30)]
+    days: i64,
+}
+
+#[derive(Parser, Debug)]
+struct ExportArgs {
+    #[command(flatten)]
+    opts: Opts,
+
+    /// number of days observations are exported for if `--from` is not given
+    #[arg(long, default_value_t = 30)]
+    days: i64,
+
+    /// first day of exported observations (eg. `2022-10-29`) in `--timezone`
+    #[arg(long)]
+    from: Option<NaiveDate>,
+
+    /// last day of exported observations (inclusive). Today if not given
+    #[arg(long)]
+    to: Option<NaiveDate>,
+
+    /// format of exported observations: csv, jsonl or columnar (JSON object of arrays)
+    #[arg(long, default_value_t = ExportFormat::Csv)]
+    format: ExportFormat,
+
+    /// averages exported observations over periods of given length (minutes)
+    #[arg(long)]
+    resample: Option<i64>,
+
+    /// file observations are exported to. Standard output if not given
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct ImportArgs {
+    #[command(flatten)]
+    opts: Opts,
+
+    /// saved station pages, CSV files (eg. made by `export`) or directories containing them
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct StatsArgs {
+    #[command(flatten)]
+    opts: Opts,
+
+    /// directory charts are saved to as PNG images
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct SessionsArgs {
+    #[command(flatten)]
+    opts: Opts,
+
+    /// number of days sessions are listed for
+    #[arg(long, default_value_t = 30)]
+    days: i64,
+}
+
 #[derive(Parser, Debug, Clone)]
 #[clap(author, version, about, long_about = None)]
 struct Opts {
@@ -36,8 +185,136 @@ struct Opts {
     #[arg(short, long, default_value_t = String::from("http://3volna.ru/anemometer/getwind?id=1"))]
     url: String,
 
-    #[arg(short, long, default_value_t = 5.0)]
+    /// Open-Meteo compatible hourly forecast url (eg. `https://api.open-meteo.com/v1/forecast?latitude=43.1&longitude=131.9&hourly=windspeed_10m,winddirection_10m,windgusts_10m&timezone=Asia/Vladivostok`)
+    #[arg(long)]
+    forecast_url: Option<String>,
+
+    /// spot name observations are stored under
+    #[arg(long, default_value_t = String::from("rvs"))]
+    spot: String,
+
+    /// TOML file with observation sources of spots (see `telewind::source`). Source of `--spot` is used instead
+    /// of `--url`, `--timezone` and `--date-format`
+    #[arg(long)]
+    sources: Option<PathBuf>,
+
+    /// IANA timezone of the station (eg. `Europe/Moscow`). Digests and forecast notices are scheduled in it too
+    #[arg(long, default_value = "Asia/Vladivostok")]
+    timezone: Tz,
+
+    /// format of observation time the station is using (see `chrono::format::strftime`)
+    #[arg(long, default_value_t = String::from(STATION_DATE_FORMAT))]
+    date_format: String,
+
+    /// wind speed threshold for the default rule (eg. `5`, `15kn`, `20km/h`)
+    #[arg(short, long, default_value = "5", value_parser = parse_speed)]
     speed: f32,
+
+    /// speed unit used to display observations
+    #[arg(long, default_value_t = SpeedUnit::MetersPerSecond)]
+    units: SpeedUnit,
+
+    /// wind sector for the default rule (eg. `NE`, `SE..SW`, `120-210`)
+    #[arg(long)]
+    sector: Option<Sector>,
+
+    /// alert rule (eg. `avg >= 7 and direction in NE..E and gust <= 14`). Overrides `--speed`
+    #[arg(short, long)]
+    rule: Option<Rule>,
+
+    /// maximum circular standard deviation of wind direction (deg.) required to raise an alert
+    #[arg(long)]
+    max_direction_spread: Option<f32>,
+
+    /// timeout (seconds) of HTTP requests to observation sources and forecast
+    #[arg(long, default_value_t = 30)]
+    http_timeout: u64,
+
+    /// user agent of HTTP requests
+    #[arg(long, default_value_t = String::from(USER_AGENT))]
+    user_agent: String,
+
+    /// proxy for HTTP requests (eg. `socks5://127.0.0.1:1080`). `HTTP_PROXY`/`HTTPS_PROXY` are used if not given
+    #[arg(long)]
+    proxy: Option<String>,
+}
+
+impl Opts {
+    /// Alert rule used for users without custom rule
+    fn default_rule(&self, sector: Sector) -> Rule {
+        let sector = self.sector.clone().unwrap_or(sector);
+        self.rule
+            .clone()
+            .unwrap_or_else(|| Rule::speed_in_sector(self.speed, sector))
+    }
+
+    /// Alert rule for a subscriber: custom rule, custom threshold within default sector or the default rule
+    fn subscriber_rule(&self, subscription: &Subscription, sector: Sector) -> Rule {
+        match (subscription.rule(), subscription.threshold) {
+            (Ok(Some(rule)), _) => rule,
+            (Ok(None), Some(threshold)) => {
+                Rule::speed_in_sector(threshold, self.sector.clone().unwrap_or(sector))
+            }
+            (Ok(None), None) => self.default_rule(sector),
+            (Err(e), _) => {
+                warn!("Invalid rule for user {}: {}", subscription.user_id, e);
+                self.default_rule(sector)
+            }
+        }
+    }
+
+    /// Observation source of the spot
+    fn source(&self) -> Result<Source> {
+        match &self.sources {
+            Some(path) => load_sources(path)?
+                .remove(&self.spot)
+                .ok_or_else(|| anyhow!("No source for spot '{}' in {}", self.spot, path.display())),
+            None => {
+                let clock = StationClock {
+                    timezone: self.timezone,
+                    date_format: self.date_format.clone(),
+                };
+                Ok(Source::html(&self.url, clock))
+            }
+        }
+    }
+
+    /// HTTP client shared by all requests
+    fn http_client(&self) -> Result<Client> {
+        http_client(
+            Duration::from_secs(self.http_timeout),
+            &self.user_agent,
+            self.proxy.as_deref(),
+        )
+    }
+
+    fn new_tracker(&self, rule: Rule, steps: u8) -> WindTracker {
+        let mut fsm = WindTracker::new(rule, steps, steps);
+        fsm.max_direction_spread = self.max_direction_spread;
+        fsm
+    }
+}
+
+impl BotArgs {
+    /// Webhook settings if webhook mode is enabled. Registered webhook requires secret token, otherwise anyone
+    /// could POST forged updates
+    fn webhook(&self) -> Result<Option<Webhook>> {
+        let url = match &self.webhook_url {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+        let secret = env::var("TELEGRAM_WEBHOOK_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty());
+        if secret.is_none() && !self.webhook_local {
+            bail!("TELEGRAM_WEBHOOK_SECRET is required in webhook mode");
+        }
+        Ok(Some(Webhook {
+            listen: self.webhook_listen,
+            url: url.clone(),
+            secret,
+        }))
+    }
 }
 
 #[derive(Debug, Subcommand)]
@@ -46,7 +323,19 @@ enum Action {
     /// parse remote url
     Parse(Opts),
     /// running telegram bot
-    RunTelegramBot(Opts),
+    RunTelegramBot(BotArgs),
+    /// fetch wind forecast (`--forecast-url`) and show good wind ranges
+    Forecast(Opts),
+    /// compare stored forecasts with observations (`--spot`, `--days`, `--speed`)
+    Accuracy(AccuracyArgs),
+    /// export stored observations of `--spot` (`--from`, `--to`, `--format`, `--resample`, `--output`)
+    Export(ExportArgs),
+    /// backfill observations of `--spot` from files. Observations already stored are skipped
+    Import(ImportArgs),
+    /// show wind statistics of `--spot` for the last year (`--speed`, `--sector`, `--rule`, `--output`)
+    Stats(StatsArgs),
+    /// list good wind sessions of `--spot` for the last `--days` days
+    Sessions(SessionsArgs),
 }
 
 #[tokio::main]
@@ -62,72 +351,357 @@ async fn main() -> Result<()> {
 
     match args.action {
         Action::Parse(opts) => run_parse(&opts).await?,
-        Action::RunTelegramBot(opts) => tg::run_bot(opts).await?,
+        Action::RunTelegramBot(args) => tg::run_bot(args).await?,
+        Action::Forecast(opts) => run_forecast(&opts).await?,
+        Action::Accuracy(args) => run_accuracy(&args).await?,
+        Action::Export(args) => run_export(&args)?,
+        Action::Import(args) => run_import(&args)?,
+        Action::Stats(args) => run_stats(&args)?,
+        Action::Sessions(args) => run_sessions(&args)?,
     }
     Ok(())
 }
 
 async fn run_parse(opts: &Opts) -> Result<()> {
-    let body = reqwest::get(&opts.url).await?.text().await?;
-
-    let mut fsm = WindTracker {
-        state: WindState::Low,
-        wind_sector: Sector::EAST_90,
-        candidate_steps: 2,
-        cooldown_steps: 2,
-        avg_speed_threshold: opts.speed,
-    };
+    let source = opts.source()?;
+    let documents = Fetcher::new(&source.url, &opts.http_client()?)
+        .fetch()
+        .await?;
 
-    let mut observations = parse(&body)?;
-    observations.reverse();
+    let mut fsm = opts.new_tracker(opts.default_rule(Sector::EAST_90), 2);
+
+    let mut observations = vec![];
+    for document in documents {
+        let parsed = source.parse(&document)?;
+        for e in &parsed.errors {
+            warn!("{}", e);
+        }
+        observations.extend(parsed.observations);
+    }
+    observations.sort_by_key(|o| o.time);
     for observation in observations {
         let event_fired = fsm.step(&observation);
         let after_state = fsm.state();
+        let observation = observation.display(opts.units);
         println!("{observation} {event_fired:>6}    {after_state:?}")
     }
 
     Ok(())
 }
 
+async fn run_forecast(opts: &Opts) -> Result<()> {
+    let url = opts
+        .forecast_url
+        .as_ref()
+        .ok_or_else(|| anyhow!("--forecast-url is required"))?;
+    let forecast = fetch_forecast(&opts.http_client()?, url).await?;
+    for hour in &forecast {
+        println!("{}", hour.display(opts.units));
+    }
+
+    let rule = opts.default_rule(Sector::NORTH_180);
+    let mut dates = forecast
+        .iter()
+        .map(|o| o.time.with_timezone(&opts.timezone).date_naive())
+        .collect::<Vec<_>>();
+    dates.dedup();
+    for date in dates {
+        let day = date.format("%d.%m.%Y").to_string();
+        match GoodWindForecast::new(date, &opts.timezone, &forecast, &rule) {
+            Some(good_wind) => println!("{}", good_wind.format(&day, opts.units)),
+            None => println!("No good wind expected {}", day),
+        }
+    }
+    Ok(())
+}
+
+async fn run_accuracy(args: &AccuracyArgs) -> Result<()> {
+    let opts = &args.opts;
+    let database_url = env::var("DATABASE_URL").context("DATABASE_URL not set")?;
+    let mut history = History::new(&database_url)?;
+    let report = accuracy_report(&mut history, &opts.spot, opts.speed, args.days, opts.units)?;
+    println!("{}", report);
+    Ok(())
+}
+
+fn run_import(args: &ImportArgs) -> Result<()> {
+    let opts = &args.opts;
+    let database_url = env::var("DATABASE_URL").context("DATABASE_URL not set")?;
+    let mut history = History::new(&database_url)?;
+    let source = opts.source()?;
+
+    let mut files = vec![];
+    for path in &args.files {
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path)?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+            entries.retain(|p| p.is_file() && !is_hidden(p));
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let (mut parsed_total, mut saved_total, mut errors_total) = (0, 0, 0);
+    for file in &files {
+        let input = fs::read_to_string(file).context(ReadingFile(file.display().to_string()))?;
+        let parsed = match import_source(&source, file).parse(&input) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!("Unable to parse {}: {}", file.display(), e);
+                errors_total += 1;
+                continue;
+            }
+        };
+        for e in &parsed.errors {
+            debug!("{}: {}", file.display(), e);
+        }
+        let saved = history.save_all(&opts.spot, &parsed.observations)?;
+        info!(
+            "{}: {} observations, {} new, {} rows skipped",
+            file.display(),
+            parsed.observations.len(),
+            saved,
+            parsed.errors.len()
+        );
+        parsed_total += parsed.observations.len();
+        saved_total += saved;
+        errors_total += parsed.errors.len();
+    }
+    println!(
+        "{} files imported: {} observations, {} new, {} duplicates, {} rows skipped",
+        files.len(),
+        parsed_total,
+        saved_total,
+        parsed_total - saved_total,
+        errors_total
+    );
+    Ok(())
+}
+
+/// Source imported file is parsed with
+///
+/// CSV files are expected in `export` layout unless the spot's source is a CSV log itself. Other files are parsed
+/// like the spot's source.
+fn import_source(source: &Source, file: &Path) -> Source {
+    let is_csv = file
+        .extension()
+        .map_or(false, |e| e.eq_ignore_ascii_case("csv"));
+    match &source.format {
+        Format::Csv(_) => source.clone(),
+        _ if is_csv => Source {
+            date_format: "%+".to_string(),
+            unit: SpeedUnit::MetersPerSecond,
+            format: Format::Csv(CsvLog::default()),
+            ..source.clone()
+        },
+        _ => source.clone(),
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .map_or(false, |name| name.to_string_lossy().starts_with('.'))
+}
+
+fn run_stats(args: &StatsArgs) -> Result<()> {
+    let opts = &args.opts;
+    let database_url = env::var("DATABASE_URL").context("DATABASE_URL not set")?;
+    let mut history = History::new(&database_url)?;
+    let rule = opts.default_rule(Sector::NORTH_180);
+    let observations = statistics_observations(&mut history, &opts.spot)?;
+    let statistics = Statistics::new(&observations, &rule);
+    println!("{}", statistics.format(&opts.spot, opts.units));
+
+    if let Some(dir) = &args.output {
+        fs::create_dir_all(dir)?;
+        let [rose, months, hours] = statistics.render()?;
+        for (name, png) in [("rose", rose), ("months", months), ("hours", hours)] {
+            let path = dir.join(format!("{}.png", name));
+            fs::write(&path, png).context(format!("Writing {}", path.display()))?;
+        }
+        info!("Charts are saved to {}", dir.display());
+    }
+    Ok(())
+}
+
+fn run_sessions(args: &SessionsArgs) -> Result<()> {
+    let opts = &args.opts;
+    let database_url = env::var("DATABASE_URL").context("DATABASE_URL not set")?;
+    let mut history = History::new(&database_url)?;
+    let report = sessions_report(&mut history, &opts.spot, args.days, opts.units)?;
+    println!("{}", report);
+    Ok(())
+}
+
+/// Sessions of the spot started in the last `days` days
+fn sessions_report(
+    history: &mut History,
+    spot: &str,
+    days: i64,
+    units: SpeedUnit,
+) -> Result<String> {
+    let to = Utc::now();
+    let from = to - chrono::Duration::days(days);
+    let sessions = history.list_sessions(spot, &from, &to)?;
+    Ok(format_sessions(spot, days, &sessions, units))
+}
+
+/// Number of days wind statistics are calculated for
+const STATISTICS_DAYS: i64 = 365;
+
+/// Observations of the spot wind statistics are calculated of (the last year)
+fn statistics_observations(history: &mut History, spot: &str) -> Result<Vec<Observation>> {
+    let to = Utc::now();
+    let from = to - chrono::Duration::days(STATISTICS_DAYS);
+    history.list(spot, &from, &to)
+}
+
+fn run_export(args: &ExportArgs) -> Result<()> {
+    let opts = &args.opts;
+    let database_url = env::var("DATABASE_URL").context("DATABASE_URL not set")?;
+    let mut history = History::new(&database_url)?;
+
+    let today = Utc::now().with_timezone(&opts.timezone).date_naive();
+    let to = args.to.unwrap_or(today);
+    let from = args
+        .from
+        .unwrap_or_else(|| to - chrono::Duration::days(args.days));
+    let start_of = |date: NaiveDate| {
+        opts.timezone
+            .from_local_datetime(&date.and_hms(0, 0, 0))
+            .earliest()
+            .ok_or_else(|| anyhow!("Invalid date {}", date))
+    };
+    let mut observations = history.list(
+        &opts.spot,
+        &start_of(from)?,
+        &start_of(to + chrono::Duration::days(1))?,
+    )?;
+    if let Some(minutes) = args.resample {
+        observations = resample(&observations, chrono::Duration::minutes(minutes));
+    }
+
+    match &args.output {
+        Some(path) => {
+            let file = File::create(path).context(format!("Creating {}", path.display()))?;
+            export(&observations, args.format, BufWriter::new(file))?;
+            info!(
+                "{} observations exported to {}",
+                observations.len(),
+                path.display()
+            );
+        }
+        None => export(&observations, args.format, io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+/// Compares forecast stored for the last `days` days with observations at a fixed horizon
+fn accuracy_report(
+    history: &mut History,
+    spot: &str,
+    threshold: f32,
+    days: i64,
+    units: SpeedUnit,
+) -> Result<String> {
+    let to = Utc::now();
+    let from = to - chrono::Duration::days(days);
+    let horizon = chrono::Duration::hours(accuracy::HORIZON_HOURS);
+    let forecast = history.list_forecast_ahead(spot, &from, &to, horizon)?;
+    let observations = history.list(spot, &from, &to)?;
+    let report = match Accuracy::new(&forecast, &observations, threshold) {
+        Some(accuracy) => accuracy.format(spot, days, units),
+        None => format!("No forecasts to compare with observations for {}", spot),
+    };
+    Ok(report)
+}
+
 /// Stream of new observations realtime
 ///
-/// Parse remote URL with given interval and return new observations one by one
-fn observation_stream(url: &str, interval: Interval) -> impl Stream<Item = Result<Observation>> {
+/// Fetch source (remote URL, local file or directory) with adaptive interval (see [`Polling`]) and return new
+/// observations one by one
+fn observation_stream(
+    name: &str,
+    source: Source,
+    client: &Client,
+    polling: Shared<Polling>,
+) -> impl Stream<Item = Result<Observation>> {
     struct State {
-        url: String,
-        interval: Interval,
+        name: String,
+        source: Source,
+        fetcher: Fetcher,
+        polling: Shared<Polling>,
+        last_fetch: Option<time::Instant>,
         // parsed but not yet processed observations in reverse order ()
         observations: Vec<Observation>,
         last_parse_time: Option<DateTime<FixedOffset>>,
     }
 
-    async fn read_data_using_http(url: &str) -> Result<String> {
-        let body = reqwest::get(url)
-            .await
-            .context(ObservationsEndpointFailed(url.to_string()))?;
-        Ok(body.text().await?)
-    }
-
     async fn next_observation(mut state: State) -> Option<(Result<Observation>, State)> {
         loop {
             if let Some(observation) = state.observations.pop() {
                 return Some((Ok(observation), state));
             }
 
-            state.interval.tick().await;
+            if let Some(last_fetch) = state.last_fetch {
+                // interval is calculated just before sleeping, so it reflects the latest tracker state
+                let interval = state.polling.lock().unwrap().next(Utc::now());
+                trace!("Next fetch of {} in {:?}", state.source.url, interval);
+                time::sleep_until(last_fetch + interval).await;
+            }
+            state.last_fetch = Some(time::Instant::now());
 
-            let response = match read_data_using_http(&state.url).await {
-                Ok(body) => body,
+            metrics::FETCH_ATTEMPTS.inc(&[&state.name]);
+            let documents = match state.fetcher.fetch().await {
+                Ok(documents) => documents,
                 Err(e) => {
-                    error!("Unable to read data from remote HTTP-endpoint. We'll keep trying...");
+                    metrics::FETCH_FAILURES.inc(&[&state.name]);
+                    state.polling.lock().unwrap().failed();
+                    error!(
+                        "Unable to read data from {}. We'll keep trying...",
+                        state.source.url
+                    );
                     warn!("{}", e);
                     continue;
                 }
             };
-            let mut last_observations = match parse(&response) {
-                Ok(observations) => observations,
-                Err(e) => return Some((Err(e), state)),
-            };
+            let mut last_observations = vec![];
+            let fetched = !documents.is_empty();
+            for document in documents {
+                let parsed = match state.source.parse(&document) {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        metrics::PARSE_ERRORS.inc(&[&state.name]);
+                        error!("Unable to parse {}: {}", state.source.url, e);
+                        continue;
+                    }
+                };
+                if !parsed.errors.is_empty() {
+                    metrics::PARSE_ERRORS.add(&[&state.name], parsed.errors.len() as f64);
+                    warn!(
+                        "{} row(s) of {} skipped or incomplete",
+                        parsed.errors.len(),
+                        state.source.url
+                    );
+                    for e in &parsed.errors {
+                        debug!("{}", e);
+                    }
+                }
+                last_observations.extend(parsed.observations);
+            }
+            // documents with nothing to parse are failures too, so a station serving garbage gets backoff.
+            // Nothing fetched (eg. not modified) is neither
+            {
+                let mut polling = state.polling.lock().unwrap();
+                if !last_observations.is_empty() {
+                    polling.observed(last_observations.iter().map(|o| o.time));
+                } else if fetched {
+                    polling.failed();
+                }
+            }
             if !last_observations.is_empty() {
                 last_observations.sort_by_key(|o| Reverse(o.time));
 
@@ -150,8 +724,11 @@ fn observation_stream(url: &str, interval: Interval) -> impl Stream<Item = Resul
     }
 
     let state = State {
-        url: url.to_owned(),
-        interval,
+        name: name.to_owned(),
+        fetcher: Fetcher::new(&source.url, client),
+        source,
+        polling,
+        last_fetch: None,
         observations: vec![],
         last_parse_time: None,
     };
@@ -161,93 +738,591 @@ fn observation_stream(url: &str, interval: Interval) -> impl Stream<Item = Resul
 
 mod tg {
     use super::*;
+    use telewind::checkin::{alert_text, CheckIn, CheckIns};
     use telewind::Subscriptions;
-    use teloxide::types::MediaText;
+    use teloxide::{
+        payloads::{AnswerCallbackQuerySetters, EditMessageTextSetters, SendMessageSetters},
+        types::{
+            CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, InputMedia,
+            InputMediaPhoto, MediaText, MessageId, ParseMode,
+        },
+    };
 
-    pub(crate) async fn run_bot(opts: Opts) -> Result<()> {
+    pub(crate) async fn run_bot(args: BotArgs) -> Result<()> {
+        let opts = args.opts.clone();
         let database_url = env::var("DATABASE_URL").context("DATABASE_URL not set")?;
         let subscriptions = Subscriptions::new(&database_url)?;
 
         let token = env::var("TELEGRAM_BOT_TOKEN").context("TELEGRAM_BOT_TOKEN not set")?;
         let bot = Arc::new(Bot::new(token));
+        let webhook = args.webhook()?;
 
         let subscriptions = Arc::new(Mutex::new(subscriptions));
+        let history = Arc::new(Mutex::new(History::new(&database_url)?));
+
+        let max_observation_age = chrono::Duration::minutes(args.max_observation_age);
+        let health = Arc::new(Health::new(max_observation_age));
+        // the bot is ready right after restart if the station is alive
+        if let Some(observation) = history.lock().unwrap().latest(&opts.spot)? {
+            health.observed(&observation.time);
+        }
+
+        let trackers = Arc::new(Mutex::new(HashMap::new()));
+        let feed = Arc::new(LiveFeed::default());
+        let api = args.api_token.as_ref().map(|token| {
+            Arc::new(Api {
+                token: token.clone(),
+                subscriptions: subscriptions.clone(),
+                history: history.clone(),
+                trackers: trackers.clone(),
+            })
+        });
+
+        let client = opts.http_client()?;
 
-        let subscription_loop_handle = tokio::task::Builder::new()
+        let (trigger, shutdown) = Shutdown::new();
+        let mut handles = vec![];
+
+        if let Some(addr) = args.http_listen {
+            let router = http::router(health.clone(), feed.clone(), api);
+            let server = http::serve(addr, router, shutdown.clone());
+            handles.push(
+                tokio::task::Builder::new()
+                    .name("http server")
+                    .spawn(async move {
+                        if let Err(e) = server.await {
+                            error!("{:?}", e);
+                        }
+                    })?,
+            );
+        }
+        let mut subscription_loop_handle = tokio::task::Builder::new()
             .name("subscription loop")
-            .spawn(subscription_loop(bot.clone(), subscriptions.clone()))?;
-        let parse_loop_handle = tokio::task::Builder::new()
-            .name("parse and notify loop")
-            .spawn(parse_and_notify_loop(opts, bot, subscriptions))?;
+            .spawn(subscription_loop(
+                args.clone(),
+                health.clone(),
+                bot.clone(),
+                subscriptions.clone(),
+                history.clone(),
+                webhook,
+                shutdown.clone(),
+            ))?;
+        let schedule_loop = {
+            let (opts, bot) = (opts.clone(), bot.clone());
+            let (subscriptions, history) = (subscriptions.clone(), history.clone());
+            let shutdown = shutdown.clone();
+            move || {
+                schedule_loop(
+                    opts.clone(),
+                    bot.clone(),
+                    subscriptions.clone(),
+                    history.clone(),
+                    shutdown.clone(),
+                )
+            }
+        };
+        handles.push(
+            tokio::task::Builder::new()
+                .name("schedule loop")
+                .spawn(supervise(
+                    "schedule loop",
+                    shutdown.clone(),
+                    backoff(),
+                    schedule_loop,
+                ))?,
+        );
+        let forecast_loop = {
+            let (opts, history, client) = (opts.clone(), history.clone(), client.clone());
+            let shutdown = shutdown.clone();
+            move || {
+                forecast_loop(
+                    opts.clone(),
+                    client.clone(),
+                    history.clone(),
+                    shutdown.clone(),
+                )
+            }
+        };
+        handles.push(
+            tokio::task::Builder::new()
+                .name("forecast loop")
+                .spawn(supervise(
+                    "forecast loop",
+                    shutdown.clone(),
+                    backoff(),
+                    forecast_loop,
+                ))?,
+        );
+        let parse_loop = {
+            let (feed, health) = (feed.clone(), health.clone());
+            let shutdown = shutdown.clone();
+            move || {
+                parse_and_notify_loop(
+                    args.clone(),
+                    bot.clone(),
+                    subscriptions.clone(),
+                    history.clone(),
+                    trackers.clone(),
+                    feed.clone(),
+                    health.clone(),
+                    client.clone(),
+                    shutdown.clone(),
+                )
+            }
+        };
+        handles.push(
+            tokio::task::Builder::new()
+                .name("parse and notify loop")
+                .spawn(supervise(
+                    "parse and notify loop",
+                    shutdown.clone(),
+                    backoff(),
+                    parse_loop,
+                ))?,
+        );
 
-        parse_loop_handle.await??;
-        subscription_loop_handle.await?;
+        let dispatcher_stopped = tokio::select! {
+            result = shutdown_signal() => {
+                result?;
+                info!("Shutting down...");
+                None
+            }
+            result = &mut subscription_loop_handle => {
+                error!("Telegram dispatcher stopped unexpectedly. Shutting down...");
+                Some(result)
+            }
+        };
+        trigger.trigger();
+        feed.close();
+        if dispatcher_stopped.is_none() {
+            handles.push(subscription_loop_handle);
+        }
 
+        // tasks finish work in progress (eg. sending notifications) before exit
+        let shutdown_timeout = Duration::from_secs(SHUTDOWN_TIMEOUT_SECS);
+        if time::timeout(shutdown_timeout, future::join_all(handles))
+            .await
+            .is_err()
+        {
+            warn!("Some tasks didn't stop in {:?}", shutdown_timeout);
+        }
+        info!("Stopped");
+
+        // non-zero exit status lets supervisor (systemd, docker) restart the bot
+        match dispatcher_stopped {
+            None => Ok(()),
+            Some(Ok(())) => bail!("Telegram dispatcher stopped unexpectedly"),
+            Some(Err(e)) => Err(e).context("Telegram dispatcher failed"),
+        }
+    }
+
+    /// Time tasks are given to finish before exit. Docker waits 10 seconds before killing container
+    const SHUTDOWN_TIMEOUT_SECS: u64 = 8;
+
+    fn backoff() -> Backoff {
+        Backoff::new(Duration::from_secs(1), Duration::from_secs(300))
+    }
+
+    /// Completes on SIGINT (Ctrl+C) or SIGTERM (`docker stop`)
+    async fn shutdown_signal() -> Result<()> {
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {},
+        }
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn parse_and_notify_loop(
-        opts: Opts,
+        args: BotArgs,
         bot: Arc<Bot>,
         subscriptions: Shared<Subscriptions>,
+        history: Shared<History>,
+        states: Shared<HashMap<i64, WindState>>,
+        feed: Arc<LiveFeed>,
+        health: Arc<Health>,
+        client: Client,
+        mut shutdown: Shutdown,
     ) -> Result<()> {
-        let mut fsm = WindTracker {
-            state: WindState::Low,
-            avg_speed_threshold: opts.speed,
-            candidate_steps: 5,
-            cooldown_steps: 5,
-            wind_sector: Sector::NORTH_180,
-        };
-        let mut interval = time::interval(Duration::from_secs(55));
-        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let opts = &args.opts;
+        // Spot-level tracker using the default rule. Its state is published to the live feed
+        let mut spot_tracker = opts.new_tracker(opts.default_rule(Sector::NORTH_180), 5);
+        // High periods of the spot tracker are saved as sessions
+        let mut sessions = SessionTracker::default();
+        let mut session_id = None;
+        // Each subscriber has its own tracker, because rules may differ
+        let mut trackers = HashMap::<i64, WindTracker>::new();
+        // Subscriptions are reloaded with every observation. The last loaded ones are used if database is
+        // unavailable, so a short database error doesn't reset trackers by restarting the loop
+        let mut subscriptions_loaded = vec![];
+        let source = opts.source()?;
+        let polling = Arc::new(Mutex::new(Polling::new(
+            Duration::from_secs(args.poll_interval),
+            Duration::from_secs(args.max_poll_interval),
+            source.timezone,
+        )));
+
+        let mut observations = Box::pin(observation_stream(
+            &opts.spot,
+            source,
+            &client,
+            polling.clone(),
+        ));
+        loop {
+            // shutdown is only checked between observations, so notifications in progress are always sent
+            let obs = tokio::select! {
+                obs = observations.next() => obs,
+                _ = shutdown.wait() => break,
+            };
+            let obs = match obs {
+                Some(obs) => obs?,
+                None => break,
+            };
+            trace!("Processing observation: {}", obs);
+
+            metrics::LAST_OBSERVATION.set(&[&opts.spot], obs.time.timestamp() as f64);
+            health.observed(&obs.time);
+            let saved = history.lock().unwrap().save(&opts.spot, &obs);
+            health.set_database_available(saved.is_ok());
+            if let Err(e) = saved {
+                error!("{:?}", e);
+            }
+
+            let before = spot_tracker.state();
+            spot_tracker.step(&obs);
+            polling.lock().unwrap().set_state(spot_tracker.state());
+            metrics::set_wind_state(&opts.spot, spot_tracker.state());
+            if let Some(event) = sessions.step(&obs, spot_tracker.state()) {
+                let mut history = history.lock().unwrap();
+                if let Err(e) = save_session(&mut history, &opts.spot, &mut session_id, event) {
+                    error!("{:?}", e);
+                }
+            }
+            feed.publish(LiveEvent::Observation {
+                spot: opts.spot.clone(),
+                observation: obs.clone(),
+            });
+            if spot_tracker.state() != before {
+                feed.publish(LiveEvent::WindState {
+                    spot: opts.spot.clone(),
+                    from: before,
+                    to: spot_tracker.state(),
+                });
+            }
 
-        let mut observations = Box::pin(observation_stream(&opts.url, interval));
-        while let Some(obs) = observations.next().await {
-            let obs = obs?;
-            let event_fired = fsm.step(&obs);
-            let after_state = fsm.state();
-            trace!("Processing observation: {} ({:?})", obs, after_state);
+            match subscriptions.lock().unwrap().list_subscriptions() {
+                Ok(subscriptions) => subscriptions_loaded = subscriptions,
+                Err(e) => error!("Unable to load subscriptions, using previous ones: {:?}", e),
+            }
+            let subscriptions = &subscriptions_loaded;
+            metrics::SUBSCRIBERS.set(&[], subscriptions.len() as f64);
+            trackers.retain(|user_id, _| {
+                let subscribed = subscriptions.iter().any(|s| s.user_id == *user_id);
+                if !subscribed {
+                    states.lock().unwrap().remove(user_id);
+                }
+                subscribed
+            });
 
-            if event_fired {
-                let users = subscriptions
+            let mut users = vec![];
+            for subscription in subscriptions {
+                let rule = opts.subscriber_rule(subscription, Sector::NORTH_180);
+                let fsm = trackers
+                    .entry(subscription.user_id)
+                    .or_insert_with(|| opts.new_tracker(rule.clone(), 5));
+                if fsm.rule != rule {
+                    *fsm = opts.new_tracker(rule, 5);
+                }
+                if fsm.step(&obs) {
+                    users.push((ChatId(subscription.user_id), subscription.speed_unit()));
+                }
+                states
                     .lock()
                     .unwrap()
-                    .list_subscriptions()?
-                    .into_iter()
-                    .map(|s| ChatId(s.user_id))
-                    .collect::<Vec<_>>();
-                tg::notify(&obs, &bot, &users[..]).await?;
+                    .insert(subscription.user_id, fsm.state());
+            }
+
+            metrics::set_tracker_states(trackers.values().map(|t| t.state()));
+
+            if !users.is_empty() {
+                tg::notify(&obs, &bot, &users[..], session_id, &history).await;
             }
         }
         Ok(())
     }
 
-    async fn subscription_loop(bot: Arc<Bot>, users: Shared<Subscriptions>) {
-        let handler =
-            dptree::entry().branch(Update::filter_message().endpoint(subscription_handler));
-        Dispatcher::builder(bot, handler)
-            .dependencies(deps![users])
-            .build()
-            .dispatch()
+    /// Saves started session and updates the one in progress
+    fn save_session(
+        history: &mut History,
+        spot: &str,
+        session_id: &mut Option<i32>,
+        event: SessionEvent,
+    ) -> Result<()> {
+        match event {
+            SessionEvent::Started(session) => {
+                info!("Session started at {}", spot);
+                *session_id = Some(history.save_session(spot, &session)?);
+            }
+            SessionEvent::Updated(session) => {
+                if let Some(id) = *session_id {
+                    history.update_session(id, spot, &session)?;
+                }
+            }
+            SessionEvent::Ended(session) => {
+                info!(
+                    "Session ended at {}: {}",
+                    spot,
+                    session.format(SpeedUnit::default())
+                );
+                if let Some(id) = session_id.take() {
+                    history.update_session(id, spot, &session)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends daily digests and forecast notices to users at the time they've chosen
+    async fn schedule_loop(
+        opts: Opts,
+        bot: Arc<Bot>,
+        subscriptions: Shared<Subscriptions>,
+        history: Shared<History>,
+        mut shutdown: Shutdown,
+    ) -> Result<()> {
+        let mut interval = time::interval(Duration::from_secs(60));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let mut last_check = Utc::now().with_timezone(&opts.timezone);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.wait() => return Ok(()),
+            }
+            let now = Utc::now().with_timezone(&opts.timezone);
+            let today = now.date_naive();
+            let is_due = |time: Option<NaiveTime>| {
+                let scheduled = time.and_then(|time| {
+                    opts.timezone
+                        .from_local_datetime(&today.and_time(time))
+                        .earliest()
+                });
+                matches!(scheduled, Some(t) if last_check < t && t <= now)
+            };
+
+            // errors don't restart the loop, otherwise messages due while restarting would be skipped
+            let subscriptions = match subscriptions.lock().unwrap().list_subscriptions() {
+                Ok(subscriptions) => subscriptions,
+                Err(e) => {
+                    // last check is kept, so messages due are sent on the next tick
+                    error!("Unable to load subscriptions: {:?}", e);
+                    continue;
+                }
+            };
+            for subscription in subscriptions {
+                let rule = opts.subscriber_rule(&subscription, Sector::NORTH_180);
+                let units = subscription.speed_unit();
+                let mut messages = vec![];
+
+                if let Some(digest_at) = subscription.digest_at().filter(|t| is_due(Some(*t))) {
+                    let date = digest_date(today, digest_at);
+                    match list_day(&history, &opts.spot, opts.timezone, date, History::list) {
+                        Ok(observations) => {
+                            messages.push(match DailySummary::new(date, &observations, &rule) {
+                                Some(summary) => summary.format(units),
+                                None => format!("No observations for {}", date.format("%d.%m.%Y")),
+                            })
+                        }
+                        Err(e) => error!("Unable to list observations for digest: {:?}", e),
+                    }
+                }
+
+                if let Some(notice_at) = subscription.forecast_at().filter(|t| is_due(Some(*t))) {
+                    let date = notice_date(today, notice_at);
+                    let forecast = list_day(
+                        &history,
+                        &opts.spot,
+                        opts.timezone,
+                        date,
+                        History::list_forecast,
+                    )
+                    .map_err(|e| error!("Unable to list forecast for notice: {:?}", e))
+                    .unwrap_or_default();
+                    let good_wind = GoodWindForecast::new(date, &opts.timezone, &forecast, &rule);
+                    if let Some(good_wind) = good_wind {
+                        let day = if notice_is_about_next_day(notice_at) {
+                            "tomorrow"
+                        } else {
+                            "today"
+                        };
+                        messages.push(good_wind.format(day, units));
+                    }
+                }
+
+                let chat_id = ChatId(subscription.user_id);
+                for message in messages {
+                    if let Err(e) = send_message(&bot, chat_id, message).await {
+                        error!("Unable to send scheduled message to {:?}: {}", chat_id, e);
+                    }
+                }
+            }
+            last_check = now;
+        }
+    }
+
+    type ListFn<Tz> =
+        fn(&mut History, &str, &DateTime<Tz>, &DateTime<Tz>) -> Result<Vec<Observation>>;
+
+    /// Lists observations (or forecasts) for a given station local date
+    fn list_day(
+        history: &Shared<History>,
+        source: &str,
+        timezone: Tz,
+        date: NaiveDate,
+        list: ListFn<Tz>,
+    ) -> Result<Vec<Observation>> {
+        let from = timezone
+            .from_local_datetime(&date.and_hms(0, 0, 0))
+            .earliest();
+        let to = timezone
+            .from_local_datetime(&date.succ().and_hms(0, 0, 0))
+            .earliest();
+        match (from, to) {
+            (Some(from), Some(to)) => list(&mut history.lock().unwrap(), source, &from, &to),
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Periodically fetches forecast and stores it
+    async fn forecast_loop(
+        opts: Opts,
+        client: Client,
+        history: Shared<History>,
+        mut shutdown: Shutdown,
+    ) -> Result<()> {
+        let url = match &opts.forecast_url {
+            Some(url) => url,
+            None => {
+                shutdown.wait().await;
+                return Ok(());
+            }
+        };
+        let mut interval = time::interval(Duration::from_secs(3600));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.wait() => return Ok(()),
+            }
+            match fetch_forecast(&client, url).await {
+                Ok(forecast) => {
+                    let saved = history.lock().unwrap().save_forecast(
+                        &opts.spot,
+                        &Utc::now(),
+                        &forecast,
+                    )?;
+                    debug!("Forecast updated: {} hours", saved);
+                }
+                Err(e) => {
+                    error!("Unable to fetch forecast. We'll keep trying...");
+                    warn!("{:?}", e);
+                }
+            }
+        }
+    }
+
+    async fn subscription_loop(
+        args: BotArgs,
+        health: Arc<Health>,
+        bot: Arc<Bot>,
+        users: Shared<Subscriptions>,
+        history: Shared<History>,
+        webhook: Option<Webhook>,
+        mut shutdown: Shutdown,
+    ) {
+        let handler = dptree::entry()
+            .branch(Update::filter_message().endpoint(subscription_handler))
+            .branch(Update::filter_callback_query().endpoint(check_in_handler));
+        let webhook_local = args.webhook_local;
+        let mut dispatcher = Dispatcher::builder(bot.clone(), handler)
+            .dependencies(deps![args, users, history])
+            .build();
+        let token = dispatcher.shutdown_token();
+        let server_shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            shutdown.wait().await;
+            // error means dispatcher is not running yet or already stopped
+            if let Ok(stopped) = token.shutdown() {
+                stopped.await;
+            }
+        });
+        health.set_dispatcher_running(true);
+        match webhook {
+            None => dispatcher.dispatch().await,
+            Some(webhook) if webhook_local => {
+                let (listener, stop, router) = webhook.local();
+                let server = http::serve(webhook.listen, router, server_shutdown);
+                dispatch_webhook(&mut dispatcher, listener, stop, server).await;
+            }
+            Some(webhook) => match webhook.setup((*bot).clone()).await {
+                Ok((listener, stop, router)) => {
+                    let server = http::serve(webhook.listen, router, server_shutdown);
+                    dispatch_webhook(&mut dispatcher, listener, stop, server).await;
+                }
+                Err(e) => error!("Unable to setup webhook: {:?}", e),
+            },
+        }
+        health.set_dispatcher_running(false);
+    }
+
+    /// Dispatches updates Telegram POSTs to webhook HTTP server
+    async fn dispatch_webhook<L>(
+        dispatcher: &mut Dispatcher<Arc<Bot>, anyhow::Error, DefaultKey>,
+        listener: L,
+        stop: impl Future<Output = ()>,
+        server: impl Future<Output = Result<()>> + Send + 'static,
+    ) where
+        L: UpdateListener<Err = Infallible>,
+    {
+        let server = tokio::task::Builder::new()
+            .name("webhook server")
+            .spawn(server)
+            .expect("Unable to spawn webhook server");
+        let error_handler = LoggingErrorHandler::with_custom_text("Webhook listener failed");
+        dispatcher
+            .dispatch_with_listener(listener, error_handler)
             .await;
+        // webhook is deleted when stop completes
+        if time::timeout(Duration::from_secs(5), stop).await.is_err() {
+            warn!("Unable to delete webhook in time");
+        }
+        match server.await {
+            Ok(Err(e)) => error!("{:?}", e),
+            Err(e) => error!("{:?}", e),
+            Ok(Ok(())) => {}
+        }
     }
 
     async fn subscription_handler(
         bot: Arc<Bot>,
         msg: Message,
+        bot_args: BotArgs,
         subscriptions: Shared<Subscriptions>,
+        history: Shared<History>,
     ) -> Result<()> {
+        let opts = &bot_args.opts;
         debug!("{:?}", &msg);
         if let ChatKind::Private { .. } = msg.chat.kind {
             let chat_id = msg.chat.id;
             if let MessageKind::Common(msg) = msg.kind {
                 if let MediaKind::Text(MediaText { text, .. }) = msg.media_kind {
-                    match text.as_str() {
+                    let (command, args) = text.split_once(' ').unwrap_or((&text, ""));
+                    match command {
                         "/subscribe" => {
                             debug!("Subscribing {:?}", chat_id);
                             subscriptions.lock().unwrap().new_subscription(chat_id.0)?;
-                            bot.send_message(chat_id, "You are subscribed sucessfully!")
-                                .await?;
+                            send_message(&bot, chat_id, "You are subscribed sucessfully!").await?;
                         }
                         "/unsubscribe" => {
                             debug!("Unsubscribing {:?}", chat_id);
@@ -255,7 +1330,53 @@ mod tg {
                                 .lock()
                                 .unwrap()
                                 .remove_subscription(chat_id.0)?;
-                            bot.send_message(chat_id, "You are unsubscribed").await?;
+                            send_message(&bot, chat_id, "You are unsubscribed").await?;
+                        }
+                        "/rule" => {
+                            let reply = rule_command(args.trim(), chat_id, &subscriptions);
+                            send_message(&bot, chat_id, reply).await?;
+                        }
+                        "/threshold" => {
+                            let reply = threshold_command(args.trim(), chat_id, &subscriptions);
+                            send_message(&bot, chat_id, reply).await?;
+                        }
+                        "/units" => {
+                            let reply = units_command(args.trim(), chat_id, &subscriptions);
+                            send_message(&bot, chat_id, reply).await?;
+                        }
+                        "/digest" => {
+                            let reply = digest_command(args.trim(), chat_id, &subscriptions);
+                            send_message(&bot, chat_id, reply).await?;
+                        }
+                        "/forecast" => {
+                            let reply = forecast_command(args.trim(), chat_id, &subscriptions);
+                            send_message(&bot, chat_id, reply).await?;
+                        }
+                        "/accuracy" => {
+                            let reply =
+                                accuracy_command(&bot_args, chat_id, &subscriptions, &history);
+                            send_message(&bot, chat_id, reply).await?;
+                        }
+                        "/sessions" => {
+                            let reply = sessions_command(
+                                args.trim(),
+                                &bot_args,
+                                chat_id,
+                                &subscriptions,
+                                &history,
+                            );
+                            send_message(&bot, chat_id, reply).await?;
+                        }
+                        "/stats" => {
+                            stats_command(
+                                &bot,
+                                args.trim(),
+                                opts,
+                                chat_id,
+                                &subscriptions,
+                                &history,
+                            )
+                            .await?;
                         }
                         _ => {}
                     }
@@ -266,21 +1387,456 @@ mod tg {
         Ok(())
     }
 
+    const NOT_SUBSCRIBED: &str = "You are not subscribed. Use /subscribe first";
+
+    /// Shows (no args), sets (`/rule <rule>`) or resets (`/rule reset`) user alert rule
+    fn rule_command(args: &str, chat_id: ChatId, subscriptions: &Shared<Subscriptions>) -> String {
+        let mut subscriptions = subscriptions.lock().unwrap();
+        let result = match args {
+            "" => {
+                subscriptions
+                    .find_subscription(chat_id.0)
+                    .map(|subscription| match subscription {
+                        Some(Subscription {
+                            rule: Some(rule), ..
+                        }) => format!("Your rule: {rule}"),
+                        Some(_) => "You are using default rule".to_string(),
+                        None => NOT_SUBSCRIBED.to_string(),
+                    })
+            }
+            "reset" => subscriptions
+                .set_rule(chat_id.0, None)
+                .map(|updated| reply_if_subscribed(updated, "Rule is reset to default")),
+            rule => match rule.parse::<Rule>() {
+                Ok(rule) => subscriptions
+                    .set_rule(chat_id.0, Some(&rule))
+                    .map(|updated| reply_if_subscribed(updated, format!("Rule is set: {rule}"))),
+                Err(e) => Ok(format!("Invalid rule: {e}")),
+            },
+        };
+        result.unwrap_or_else(|e| {
+            error!("{:?}", e);
+            "Unable to update rule. Try again later".to_string()
+        })
+    }
+
+    /// Shows (no args), sets (`/threshold 15kn`) or resets (`/threshold reset`) user speed threshold
+    fn threshold_command(
+        args: &str,
+        chat_id: ChatId,
+        subscriptions: &Shared<Subscriptions>,
+    ) -> String {
+        let mut subscriptions = subscriptions.lock().unwrap();
+        let result = match args {
+            "" => {
+                subscriptions
+                    .find_subscription(chat_id.0)
+                    .map(|subscription| match subscription {
+                        Some(
+                            s @ Subscription {
+                                threshold: Some(threshold),
+                                ..
+                            },
+                        ) => format!("Your threshold: {}", s.speed_unit().format(threshold)),
+                        Some(_) => "You are using default threshold".to_string(),
+                        None => NOT_SUBSCRIBED.to_string(),
+                    })
+            }
+            "reset" => subscriptions
+                .set_threshold(chat_id.0, None)
+                .map(|updated| reply_if_subscribed(updated, "Threshold is reset to default")),
+            threshold => match parse_speed(threshold) {
+                Ok(speed) => subscriptions
+                    .set_threshold(chat_id.0, Some(speed))
+                    .map(|updated| {
+                        reply_if_subscribed(updated, format!("Threshold is set: {threshold}"))
+                    }),
+                Err(e) => Ok(format!("Invalid threshold: {e}")),
+            },
+        };
+        result.unwrap_or_else(|e| {
+            error!("{:?}", e);
+            "Unable to update threshold. Try again later".to_string()
+        })
+    }
+
+    /// Shows (no args) or sets (`/units kn`) speed unit used in messages
+    fn units_command(args: &str, chat_id: ChatId, subscriptions: &Shared<Subscriptions>) -> String {
+        let mut subscriptions = subscriptions.lock().unwrap();
+        let result = match args {
+            "" => {
+                subscriptions
+                    .find_subscription(chat_id.0)
+                    .map(|subscription| match subscription {
+                        Some(s) => format!("Your units: {}", s.speed_unit()),
+                        None => NOT_SUBSCRIBED.to_string(),
+                    })
+            }
+            unit => match unit.parse::<SpeedUnit>() {
+                Ok(unit) => subscriptions
+                    .set_speed_unit(chat_id.0, unit)
+                    .map(|updated| reply_if_subscribed(updated, format!("Units are set: {unit}"))),
+                Err(e) => Ok(e.to_string()),
+            },
+        };
+        result.unwrap_or_else(|e| {
+            error!("{:?}", e);
+            "Unable to update units. Try again later".to_string()
+        })
+    }
+
+    /// Shows (no args), sets (`/digest 21:00`) or disables (`/digest off`) daily digest
+    fn digest_command(
+        args: &str,
+        chat_id: ChatId,
+        subscriptions: &Shared<Subscriptions>,
+    ) -> String {
+        let mut subscriptions = subscriptions.lock().unwrap();
+        let result = match args {
+            "" => subscriptions
+                .find_subscription(chat_id.0)
+                .map(|subscription| match subscription.map(|s| s.digest_at()) {
+                    Some(Some(time)) => format!("Daily digest is sent at {}", time.format("%H:%M")),
+                    Some(None) => "Daily digest is off. Use /digest HH:MM to enable it".to_string(),
+                    None => NOT_SUBSCRIBED.to_string(),
+                }),
+            "off" => subscriptions
+                .set_digest_time(chat_id.0, None)
+                .map(|updated| reply_if_subscribed(updated, "Daily digest is off")),
+            time => match NaiveTime::parse_from_str(time, "%H:%M") {
+                Ok(time) => subscriptions
+                    .set_digest_time(chat_id.0, Some(time))
+                    .map(|updated| {
+                        let day = if summarizes_previous_day(time) {
+                            "yesterday's"
+                        } else {
+                            "today's"
+                        };
+                        let reply = format!(
+                            "Daily digest of {day} wind will be sent at {} (station local time)",
+                            time.format("%H:%M")
+                        );
+                        reply_if_subscribed(updated, reply)
+                    }),
+                Err(_) => Ok("Invalid time. Use /digest HH:MM or /digest off".to_string()),
+            },
+        };
+        result.unwrap_or_else(|e| {
+            error!("{:?}", e);
+            "Unable to update digest settings. Try again later".to_string()
+        })
+    }
+
+    /// Shows (no args), sets (`/forecast 20:00`) or disables (`/forecast off`) good wind forecast notices
+    fn forecast_command(
+        args: &str,
+        chat_id: ChatId,
+        subscriptions: &Shared<Subscriptions>,
+    ) -> String {
+        let mut subscriptions = subscriptions.lock().unwrap();
+        let result = match args {
+            "" => subscriptions
+                .find_subscription(chat_id.0)
+                .map(|subscription| match subscription.map(|s| s.forecast_at()) {
+                    Some(Some(time)) => {
+                        format!("Forecast notices are sent at {}", time.format("%H:%M"))
+                    }
+                    Some(None) => {
+                        "Forecast notices are off. Use /forecast HH:MM to enable them".to_string()
+                    }
+                    None => NOT_SUBSCRIBED.to_string(),
+                }),
+            "off" => subscriptions
+                .set_forecast_time(chat_id.0, None)
+                .map(|updated| reply_if_subscribed(updated, "Forecast notices are off")),
+            time => match NaiveTime::parse_from_str(time, "%H:%M") {
+                Ok(time) => subscriptions
+                    .set_forecast_time(chat_id.0, Some(time))
+                    .map(|updated| {
+                        let day = if notice_is_about_next_day(time) {
+                            "tomorrow"
+                        } else {
+                            "today"
+                        };
+                        let reply = format!(
+                            "If good wind is expected {day}, you'll be notified at {} (station local time)",
+                            time.format("%H:%M")
+                        );
+                        reply_if_subscribed(updated, reply)
+                    }),
+                Err(_) => Ok("Invalid time. Use /forecast HH:MM or /forecast off".to_string()),
+            },
+        };
+        result.unwrap_or_else(|e| {
+            error!("{:?}", e);
+            "Unable to update forecast settings. Try again later".to_string()
+        })
+    }
+
+    /// Shows how forecast compares with observations using user threshold
+    fn accuracy_command(
+        bot_args: &BotArgs,
+        chat_id: ChatId,
+        subscriptions: &Shared<Subscriptions>,
+        history: &Shared<History>,
+    ) -> String {
+        let opts = &bot_args.opts;
+        let subscription = subscriptions.lock().unwrap().find_subscription(chat_id.0);
+        let (threshold, units) = match &subscription {
+            Ok(Some(s)) => (s.threshold.unwrap_or(opts.speed), s.speed_unit()),
+            _ => (opts.speed, SpeedUnit::default()),
+        };
+        let mut history = history.lock().unwrap();
+        accuracy_report(&mut history, &opts.spot, threshold, bot_args.days, units).unwrap_or_else(
+            |e| {
+                error!("{:?}", e);
+                "Unable to calculate forecast accuracy. Try again later".to_string()
+            },
+        )
+    }
+
+    /// Lists sessions of the last `--days` days (no args) or given number of days (`/sessions 90`)
+    fn sessions_command(
+        args: &str,
+        bot_args: &BotArgs,
+        chat_id: ChatId,
+        subscriptions: &Shared<Subscriptions>,
+        history: &Shared<History>,
+    ) -> String {
+        let days = match args {
+            "" => bot_args.days,
+            days => match days.parse::<i64>() {
+                Ok(days) if days > 0 => days,
+                _ => return "Invalid number of days. Use /sessions or /sessions 90".to_string(),
+            },
+        };
+        let units = match subscriptions.lock().unwrap().find_subscription(chat_id.0) {
+            Ok(Some(s)) => s.speed_unit(),
+            _ => SpeedUnit::default(),
+        };
+        let mut history = history.lock().unwrap();
+        sessions_report(&mut history, &bot_args.opts.spot, days, units).unwrap_or_else(|e| {
+            error!("{:?}", e);
+            "Unable to list sessions. Try again later".to_string()
+        })
+    }
+
+    /// Sends wind statistics of the spot (no args) or other spot (`/stats <spot>`) as text and charts
+    ///
+    /// Rideable hours are counted using user rule
+    async fn stats_command(
+        bot: &Bot,
+        args: &str,
+        opts: &Opts,
+        chat_id: ChatId,
+        subscriptions: &Shared<Subscriptions>,
+        history: &Shared<History>,
+    ) -> Result<()> {
+        let spot = if args.is_empty() {
+            opts.spot.as_str()
+        } else {
+            args
+        };
+        let subscription = subscriptions.lock().unwrap().find_subscription(chat_id.0);
+        let (rule, units) = match &subscription {
+            Ok(Some(s)) => (opts.subscriber_rule(s, Sector::NORTH_180), s.speed_unit()),
+            _ => (opts.default_rule(Sector::NORTH_180), SpeedUnit::default()),
+        };
+        // a year of observations takes a while to load and process, so async runtime is not blocked
+        let (history, spot_name) = (history.clone(), spot.to_string());
+        let result = tokio::task::spawn_blocking(move || {
+            let observations = statistics_observations(&mut history.lock().unwrap(), &spot_name)?;
+            let statistics = Statistics::new(&observations, &rule);
+            let charts = statistics.render()?;
+            Ok::<_, anyhow::Error>((statistics, charts))
+        })
+        .await?;
+        let (statistics, charts) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                error!("{:?}", e);
+                let reply = "Unable to calculate wind statistics. Try again later";
+                send_message(bot, chat_id, reply).await?;
+                return Ok(());
+            }
+        };
+
+        // monospace, so tables are aligned
+        let report = format!("```\n{}\n```", escape_pre(&statistics.format(spot, units)));
+        let started = Instant::now();
+        bot.send_message(chat_id, report)
+            .parse_mode(ParseMode::MarkdownV2)
+            .await?;
+        metrics::TELEGRAM_LATENCY.observe(&["sendMessage"], started.elapsed());
+        if statistics.rose.total == 0 {
+            return Ok(());
+        }
+
+        let [rose, months, hours] = charts;
+        let captions = [
+            format!(
+                "Wind rose of {spot} (north is up). Speed {} {units}, light to dark",
+                speed_bin_labels(units).join(", ")
+            ),
+            format!(
+                "Rideable hours of {spot} by month, January to December (grid lines every 10%)"
+            ),
+            format!("Rideable hours of {spot} by time of day, 00 to 23 h (grid lines every 10%)"),
+        ];
+        let media = [rose, months, hours]
+            .into_iter()
+            .zip(captions)
+            .map(|(png, caption)| {
+                InputMedia::Photo(
+                    InputMediaPhoto::new(InputFile::memory(png).file_name("chart.png"))
+                        .caption(caption),
+                )
+            });
+        let started = Instant::now();
+        bot.send_media_group(chat_id, media).await?;
+        metrics::TELEGRAM_LATENCY.observe(&["sendMediaGroup"], started.elapsed());
+        Ok(())
+    }
+
+    /// Escapes text of MarkdownV2 `pre` block (spot name is given by user)
+    fn escape_pre(text: &str) -> String {
+        text.replace('\\', "\\\\").replace('`', "\\`")
+    }
+
+    /// Check-in buttons showing number of riders checked in
+    fn check_in_keyboard(session_id: i32, check_ins: &CheckIns) -> InlineKeyboardMarkup {
+        let buttons = CheckIn::ALL.map(|check_in| {
+            let label = match check_ins.count(check_in) {
+                0 => check_in.label().to_string(),
+                count => format!("{} ({})", check_in.label(), count),
+            };
+            InlineKeyboardButton::callback(label, check_in.callback_data(session_id))
+        });
+        InlineKeyboardMarkup::new([buttons])
+    }
+
+    /// Saves check-in and updates counts in all alerts of the session
+    async fn check_in_handler(
+        bot: Arc<Bot>,
+        query: CallbackQuery,
+        history: Shared<History>,
+    ) -> Result<()> {
+        let (session_id, check_in) =
+            match query.data.as_deref().and_then(CheckIn::parse_callback_data) {
+                Some(data) => data,
+                None => {
+                    bot.answer_callback_query(query.id).await?;
+                    return Ok(());
+                }
+            };
+        debug!(
+            "User {} checked in to session {}: {:?}",
+            query.from.id, session_id, check_in
+        );
+
+        let name = query.from.full_name();
+        let result = (|| {
+            let mut history = history.lock().unwrap();
+            history.check_in(session_id, query.from.id.0 as i64, &name, check_in)?;
+            Ok::<_, anyhow::Error>((history.check_ins(session_id)?, history.alerts(session_id)?))
+        })();
+        // query is answered even on errors, otherwise the client keeps waiting
+        let text = match result {
+            Ok(_) => check_in.label(),
+            Err(_) => "Unable to check in. Try again later",
+        };
+        bot.answer_callback_query(query.id).text(text).await?;
+        let (check_ins, alerts) = result?;
+
+        for alert in alerts {
+            let started = Instant::now();
+            let result = bot
+                .edit_message_text(
+                    ChatId(alert.chat_id),
+                    MessageId(alert.message_id),
+                    alert_text(&alert.text, &check_ins),
+                )
+                .reply_markup(check_in_keyboard(session_id, &check_ins))
+                .await;
+            metrics::TELEGRAM_LATENCY.observe(&["editMessageText"], started.elapsed());
+            // message could be deleted by user, others should be updated anyway
+            if let Err(e) = result {
+                debug!("Unable to update alert {:?}: {}", alert, e);
+            }
+        }
+        Ok(())
+    }
+
+    fn reply_if_subscribed(updated: bool, reply: impl Into<String>) -> String {
+        if updated {
+            reply.into()
+        } else {
+            NOT_SUBSCRIBED.to_string()
+        }
+    }
+
+    /// Sends text message measuring Telegram API latency
+    pub(crate) async fn send_message(
+        bot: &Bot,
+        chat_id: ChatId,
+        text: impl Into<String>,
+    ) -> std::result::Result<Message, RequestError> {
+        let started = Instant::now();
+        let result = bot.send_message(chat_id, text).await;
+        metrics::TELEGRAM_LATENCY.observe(&["sendMessage"], started.elapsed());
+        result
+    }
+
+    /// Sends alerts. Alerts sent during a session of the spot have check-in buttons
+    ///
+    /// Sessions follow the spot tracker only, so they stay the spot's good wind periods. Alert of a rule matching
+    /// outside of a session (eg. lower speed threshold than the spot's one) has no buttons.
     pub(crate) async fn notify(
         observation: &Observation,
         bot: &Bot,
-        users: &[ChatId],
-    ) -> Result<()> {
+        users: &[(ChatId, SpeedUnit)],
+        session_id: Option<i32>,
+        history: &Shared<History>,
+    ) {
         warn!(
             "Wind is growing up: {observation}. Sending notifications to {} users",
             users.len()
         );
 
-        let message = format!("Wind is growing up: {observation}");
-        for chat in users.iter() {
-            bot.send_message(*chat, &message).await?;
+        // alerts are sent even if check-ins can't be loaded
+        let check_ins = match session_id.map(|id| history.lock().unwrap().check_ins(id)) {
+            Some(Ok(check_ins)) => check_ins,
+            Some(Err(e)) => {
+                error!("{:?}", e);
+                CheckIns::default()
+            }
+            None => CheckIns::default(),
+        };
+        for (chat, units) in users.iter() {
+            let alert = format!("Wind is growing up: {}", observation.display(*units));
+            let started = Instant::now();
+            let mut request = bot.send_message(*chat, alert_text(&alert, &check_ins));
+            if let Some(id) = session_id {
+                request = request.reply_markup(check_in_keyboard(id, &check_ins));
+            }
+            let result = request.await;
+            metrics::TELEGRAM_LATENCY.observe(&["sendMessage"], started.elapsed());
+            match result {
+                Ok(message) => {
+                    metrics::NOTIFICATIONS_SENT.inc(&[]);
+                    if let Some(id) = session_id {
+                        let mut history = history.lock().unwrap();
+                        if let Err(e) = history.save_alert(id, chat.0, message.id.0, &alert) {
+                            error!("{:?}", e);
+                        }
+                    }
+                }
+                // one failed chat shouldn't prevent others from being notified
+                Err(e) => {
+                    metrics::NOTIFICATIONS_FAILED.inc(&[]);
+                    error!("Unable to notify {:?}: {}", chat, e);
+                }
+            }
         }
-
-        Ok(())
     }
 }
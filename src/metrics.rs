@@ -0,0 +1,309 @@
+//! Prometheus metrics
+//!
+//! Metrics are global, so any part of the bot can update them without threading a registry around.
+//! [`render()`] returns all metrics in Prometheus text exposition format.
+use crate::WindState;
+use chrono::Utc;
+use lazy_static::lazy_static;
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+lazy_static! {
+    pub static ref FETCH_ATTEMPTS: Family = Family::counter(
+        "telewind_fetch_attempts_total",
+        "Number of observation endpoint requests",
+        &["source"]
+    );
+    pub static ref FETCH_FAILURES: Family = Family::counter(
+        "telewind_fetch_failures_total",
+        "Number of failed observation endpoint requests",
+        &["source"]
+    );
+    pub static ref PARSE_ERRORS: Family = Family::counter(
+        "telewind_parse_errors_total",
+        "Number of observation documents failed to parse plus rows skipped or incomplete in parsed ones",
+        &["source"]
+    );
+    pub static ref LAST_OBSERVATION: Family = Family::gauge(
+        "telewind_last_observation_timestamp_seconds",
+        "Unix time of the last observation",
+        &["source"]
+    );
+    pub static ref WIND_STATE: Family = Family::gauge(
+        "telewind_wind_state",
+        "Current state of the spot wind tracker (1 for current state, 0 otherwise)",
+        &["source", "state"]
+    );
+    pub static ref TRACKERS: Family = Family::gauge(
+        "telewind_wind_trackers",
+        "Number of subscriber wind trackers by state",
+        &["state"]
+    );
+    pub static ref NOTIFICATIONS_SENT: Family = Family::counter(
+        "telewind_notifications_sent_total",
+        "Number of wind notifications sent",
+        &[]
+    );
+    pub static ref NOTIFICATIONS_FAILED: Family = Family::counter(
+        "telewind_notifications_failed_total",
+        "Number of wind notifications failed to send",
+        &[]
+    );
+    pub static ref SUBSCRIBERS: Family =
+        Family::gauge("telewind_subscribers", "Number of subscribers", &[]);
+    pub static ref TELEGRAM_LATENCY: Summary = Summary::new(
+        "telewind_telegram_request_duration_seconds",
+        "Telegram Bot API request duration",
+        &["method"]
+    );
+}
+
+const WIND_STATES: [&str; 4] = ["low", "candidate", "high", "cooldown"];
+
+/// Group of metrics with the same name and different label values
+pub struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, f64>>,
+}
+
+impl Family {
+    pub fn counter(
+        name: &'static str,
+        help: &'static str,
+        labels: &'static [&'static str],
+    ) -> Self {
+        Self::new(name, help, "counter", labels)
+    }
+
+    pub fn gauge(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self::new(name, help, "gauge", labels)
+    }
+
+    fn new(
+        name: &'static str,
+        help: &'static str,
+        kind: &'static str,
+        labels: &'static [&'static str],
+    ) -> Self {
+        Self {
+            name,
+            help,
+            kind,
+            labels,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self, labels: &[&str]) {
+        self.add(labels, 1.);
+    }
+
+    pub fn add(&self, labels: &[&str], value: f64) {
+        *self.values.lock().unwrap().entry(key(labels)).or_default() += value;
+    }
+
+    pub fn set(&self, labels: &[&str], value: f64) {
+        self.values.lock().unwrap().insert(key(labels), value);
+    }
+
+    pub fn get(&self, labels: &[&str]) -> Option<f64> {
+        self.values.lock().unwrap().get(&key(labels)).copied()
+    }
+
+    fn render(&self, out: &mut String) {
+        render_header(out, self.name, self.help, self.kind);
+        for (values, value) in self.values.lock().unwrap().iter() {
+            render_sample(out, self.name, self.labels, values, *value);
+        }
+    }
+}
+
+/// Sum and count of observed values (eg. request durations)
+pub struct Summary {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, (f64, u64)>>,
+}
+
+impl Summary {
+    pub fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, labels: &[&str], duration: Duration) {
+        let mut values = self.values.lock().unwrap();
+        let (sum, count) = values.entry(key(labels)).or_default();
+        *sum += duration.as_secs_f64();
+        *count += 1;
+    }
+
+    fn render(&self, out: &mut String) {
+        render_header(out, self.name, self.help, "summary");
+        for (values, (sum, count)) in self.values.lock().unwrap().iter() {
+            let name = format!("{}_sum", self.name);
+            render_sample(out, &name, self.labels, values, *sum);
+            let name = format!("{}_count", self.name);
+            render_sample(out, &name, self.labels, values, *count as f64);
+        }
+    }
+}
+
+fn state_name(state: WindState) -> &'static str {
+    match state {
+        WindState::Low => "low",
+        WindState::Candidate(_) => "candidate",
+        WindState::High => "high",
+        WindState::Cooldown(_) => "cooldown",
+    }
+}
+
+/// Marks current state of the spot tracker
+pub fn set_wind_state(source: &str, state: WindState) {
+    let current = state_name(state);
+    for name in WIND_STATES {
+        let value = if name == current { 1. } else { 0. };
+        WIND_STATE.set(&[source, name], value);
+    }
+}
+
+/// Counts subscriber trackers by state. Subscribers are not exposed, so the number of metrics doesn't grow with them
+pub fn set_tracker_states(states: impl IntoIterator<Item = WindState>) {
+    let mut counts = [0; WIND_STATES.len()];
+    for state in states {
+        if let Some(idx) = WIND_STATES.iter().position(|s| *s == state_name(state)) {
+            counts[idx] += 1;
+        }
+    }
+    for (name, count) in WIND_STATES.iter().zip(counts) {
+        TRACKERS.set(&[name], count as f64);
+    }
+}
+
+/// Renders all metrics in Prometheus text format
+pub fn render() -> String {
+    render_at(Utc::now().timestamp())
+}
+
+fn render_at(now: i64) -> String {
+    let mut out = String::new();
+    FETCH_ATTEMPTS.render(&mut out);
+    FETCH_FAILURES.render(&mut out);
+    PARSE_ERRORS.render(&mut out);
+    LAST_OBSERVATION.render(&mut out);
+
+    // age is derived from the last observation time, so it is up to date on every scrape
+    let name = "telewind_last_observation_age_seconds";
+    render_header(
+        &mut out,
+        name,
+        "Seconds since the last observation",
+        "gauge",
+    );
+    for (values, time) in LAST_OBSERVATION.values.lock().unwrap().iter() {
+        render_sample(
+            &mut out,
+            name,
+            LAST_OBSERVATION.labels,
+            values,
+            now as f64 - time,
+        );
+    }
+
+    WIND_STATE.render(&mut out);
+    TRACKERS.render(&mut out);
+    NOTIFICATIONS_SENT.render(&mut out);
+    NOTIFICATIONS_FAILED.render(&mut out);
+    SUBSCRIBERS.render(&mut out);
+    TELEGRAM_LATENCY.render(&mut out);
+    out
+}
+
+fn key(labels: &[&str]) -> Vec<String> {
+    labels.iter().map(|l| l.to_string()).collect()
+}
+
+fn render_header(out: &mut String, name: &str, help: &str, kind: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+fn render_sample(out: &mut String, name: &str, labels: &[&str], values: &[String], value: f64) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels = labels
+            .iter()
+            .zip(values)
+            .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+            .collect::<Vec<_>>();
+        write!(out, "{{{}}}", labels.join(",")).unwrap();
+    }
+    writeln!(out, " {}", value).unwrap();
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn family_rendering() {
+        let family = Family::counter("test_total", "Test counter", &["source", "kind"]);
+        family.inc(&["rvs", "a"]);
+        family.add(&["rvs", "a"], 2.);
+        family.inc(&["other \"spot\"", "b"]);
+
+        let mut out = String::new();
+        family.render(&mut out);
+        assert_eq!(
+            "# HELP test_total Test counter\n\
+            # TYPE test_total counter\n\
+            test_total{source=\"other \\\"spot\\\"\",kind=\"b\"} 1\n\
+            test_total{source=\"rvs\",kind=\"a\"} 3\n",
+            out
+        );
+        assert_eq!(Some(3.), family.get(&["rvs", "a"]));
+    }
+
+    #[test]
+    fn summary_rendering() {
+        let summary = Summary::new("test_seconds", "Test summary", &[]);
+        summary.observe(&[], Duration::from_millis(250));
+        summary.observe(&[], Duration::from_millis(500));
+
+        let mut out = String::new();
+        summary.render(&mut out);
+        assert!(out.contains("# TYPE test_seconds summary\n"));
+        assert!(out.contains("test_seconds_sum 0.75\n"));
+        assert!(out.contains("test_seconds_count 2\n"));
+    }
+
+    #[test]
+    fn wind_state_and_observation_age() {
+        set_wind_state("metrics-test", WindState::Candidate(2));
+        assert_eq!(Some(1.), WIND_STATE.get(&["metrics-test", "candidate"]));
+        assert_eq!(Some(0.), WIND_STATE.get(&["metrics-test", "low"]));
+        set_tracker_states([WindState::High, WindState::Low, WindState::High]);
+
+        LAST_OBSERVATION.set(&["metrics-test"], 1000.);
+        let out = render_at(1060);
+        assert!(out.contains("telewind_last_observation_age_seconds{source=\"metrics-test\"} 60\n"));
+        assert!(out.contains("telewind_wind_state{source=\"metrics-test\",state=\"high\"} 0\n"));
+        assert!(out.contains("telewind_wind_trackers{state=\"high\"} 2\n"));
+        assert!(out.contains("telewind_wind_trackers{state=\"cooldown\"} 0\n"));
+    }
+}
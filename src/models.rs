@@ -1,11 +1,56 @@
+use crate::{
+    parser::Observation,
+    prelude::*,
+    rule::Rule,
+    schema::{alerts, check_ins, forecasts, observations, sessions, subscriptions},
+    session::Session,
+    units::SpeedUnit,
+};
+use anyhow::anyhow;
+use chrono::{DateTime, FixedOffset, NaiveTime, TimeZone};
 use diesel::prelude::*;
-use crate::schema::subscriptions;
+use serde::Serialize;
 
-#[derive(Queryable)]
+#[derive(Queryable, Serialize)]
 pub struct Subscription {
     pub id: i32,
     pub user_id: i64,
     pub created_at: i64,
+    /// Custom alert rule in a text form (see [`crate::rule::Rule`])
+    pub rule: Option<String>,
+    /// Alert speed threshold (m/s)
+    pub threshold: Option<f32>,
+    /// Speed unit user prefers (see [`SpeedUnit`])
+    pub speed_unit: Option<String>,
+    /// Station local time (`HH:MM`) daily digest is sent at
+    pub digest_at: Option<String>,
+    /// Station local time (`HH:MM`) good wind forecast notice is sent at
+    pub forecast_at: Option<String>,
+}
+
+impl Subscription {
+    pub fn speed_unit(&self) -> SpeedUnit {
+        self.speed_unit
+            .as_deref()
+            .and_then(|unit| unit.parse().ok())
+            .unwrap_or_default()
+    }
+
+    pub fn rule(&self) -> Result<Option<Rule>> {
+        self.rule.as_deref().map(str::parse).transpose()
+    }
+
+    pub fn digest_at(&self) -> Option<NaiveTime> {
+        parse_time(self.digest_at.as_deref())
+    }
+
+    pub fn forecast_at(&self) -> Option<NaiveTime> {
+        parse_time(self.forecast_at.as_deref())
+    }
+}
+
+fn parse_time(time: Option<&str>) -> Option<NaiveTime> {
+    time.and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
 }
 
 #[derive(Insertable)]
@@ -13,4 +58,203 @@ pub struct Subscription {
 pub struct NewSubscription {
     pub user_id: i64,
     pub created_at: i64,
-}
\ No newline at end of file
+}
+
+#[derive(Queryable)]
+pub struct ObservationRecord {
+    pub id: i32,
+    pub source: String,
+    /// Unix timestamp
+    pub time: i64,
+    /// Offset from UTC (seconds) of the station observation was made at
+    pub utc_offset: i32,
+    pub direction: Option<i32>,
+    pub avg_speed: f32,
+    pub gust_speed: Option<f32>,
+}
+
+impl TryFrom<ObservationRecord> for Observation {
+    type Error = anyhow::Error;
+
+    fn try_from(record: ObservationRecord) -> Result<Self> {
+        Ok(Observation {
+            time: to_datetime(record.time, record.utc_offset)?,
+            direction: record.direction.map(u16::try_from).transpose()?,
+            avg_speed: record.avg_speed,
+            gust_speed: record.gust_speed,
+        })
+    }
+}
+
+fn to_datetime(timestamp: i64, utc_offset: i32) -> Result<DateTime<FixedOffset>> {
+    FixedOffset::east_opt(utc_offset)
+        .and_then(|offset| offset.timestamp_opt(timestamp, 0).single())
+        .ok_or_else(|| anyhow!("Invalid time: {} ({})", timestamp, utc_offset))
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = observations)]
+pub struct NewObservation<'a> {
+    pub source: &'a str,
+    pub time: i64,
+    pub utc_offset: i32,
+    pub direction: Option<i32>,
+    pub avg_speed: f32,
+    pub gust_speed: Option<f32>,
+}
+
+impl<'a> NewObservation<'a> {
+    pub fn new(source: &'a str, observation: &Observation) -> Self {
+        Self {
+            source,
+            time: observation.time.timestamp(),
+            utc_offset: observation.time.offset().local_minus_utc(),
+            direction: observation.direction.map(i32::from),
+            avg_speed: observation.avg_speed,
+            gust_speed: observation.gust_speed,
+        }
+    }
+}
+
+#[derive(Queryable)]
+pub struct ForecastRecord {
+    pub id: i32,
+    pub source: String,
+    /// Unix timestamp of forecasted hour
+    pub time: i64,
+    pub utc_offset: i32,
+    /// Unix timestamp forecast was fetched at
+    pub fetched_at: i64,
+    pub direction: Option<i32>,
+    pub avg_speed: f32,
+    pub gust_speed: Option<f32>,
+}
+
+impl TryFrom<ForecastRecord> for Observation {
+    type Error = anyhow::Error;
+
+    fn try_from(record: ForecastRecord) -> Result<Self> {
+        Ok(Observation {
+            time: to_datetime(record.time, record.utc_offset)?,
+            direction: record.direction.map(u16::try_from).transpose()?,
+            avg_speed: record.avg_speed,
+            gust_speed: record.gust_speed,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = forecasts)]
+pub struct NewForecast<'a> {
+    pub source: &'a str,
+    pub time: i64,
+    pub utc_offset: i32,
+    pub fetched_at: i64,
+    pub direction: Option<i32>,
+    pub avg_speed: f32,
+    pub gust_speed: Option<f32>,
+}
+
+impl<'a> NewForecast<'a> {
+    pub fn new(source: &'a str, fetched_at: i64, forecast: &Observation) -> Self {
+        Self {
+            source,
+            time: forecast.time.timestamp(),
+            utc_offset: forecast.time.offset().local_minus_utc(),
+            fetched_at,
+            direction: forecast.direction.map(i32::from),
+            avg_speed: forecast.avg_speed,
+            gust_speed: forecast.gust_speed,
+        }
+    }
+}
+
+#[derive(Queryable)]
+pub struct SessionRecord {
+    pub id: i32,
+    pub source: String,
+    /// Unix timestamp of the first observation
+    pub started_at: i64,
+    /// Unix timestamp of the last observation
+    pub ended_at: i64,
+    pub utc_offset: i32,
+    pub peak_speed: f32,
+    pub avg_speed: f32,
+    pub direction: Option<i32>,
+    pub observations: i32,
+}
+
+impl TryFrom<SessionRecord> for Session {
+    type Error = anyhow::Error;
+
+    fn try_from(record: SessionRecord) -> Result<Self> {
+        Ok(Session {
+            start: to_datetime(record.started_at, record.utc_offset)?,
+            end: to_datetime(record.ended_at, record.utc_offset)?,
+            peak_speed: record.peak_speed,
+            avg_speed: record.avg_speed,
+            direction: record.direction.map(u16::try_from).transpose()?,
+            observations: u32::try_from(record.observations)?,
+        })
+    }
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = sessions)]
+pub struct NewSession<'a> {
+    pub source: &'a str,
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub utc_offset: i32,
+    pub peak_speed: f32,
+    pub avg_speed: f32,
+    pub direction: Option<i32>,
+    pub observations: i32,
+}
+
+impl<'a> NewSession<'a> {
+    pub fn new(source: &'a str, session: &Session) -> Self {
+        Self {
+            source,
+            started_at: session.start.timestamp(),
+            ended_at: session.end.timestamp(),
+            utc_offset: session.start.offset().local_minus_utc(),
+            peak_speed: session.peak_speed,
+            avg_speed: session.avg_speed,
+            direction: session.direction.map(i32::from),
+            observations: session.observations as i32,
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = check_ins)]
+pub struct NewCheckIn<'a> {
+    pub session_id: i32,
+    pub user_id: i64,
+    /// Name of the user shown to other riders
+    pub name: &'a str,
+    /// Check-in status (see [`crate::checkin::CheckIn`])
+    pub status: &'a str,
+    pub updated_at: i64,
+}
+
+/// Alert message sent to a user during a session. Its check-in counts are updated when someone checks in
+#[derive(Queryable, Debug, PartialEq, Eq)]
+pub struct Alert {
+    pub id: i32,
+    pub session_id: i32,
+    pub chat_id: i64,
+    pub message_id: i32,
+    /// Alert text without check-ins
+    pub text: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = alerts)]
+pub struct NewAlert<'a> {
+    pub session_id: i32,
+    pub chat_id: i64,
+    pub message_id: i32,
+    pub text: &'a str,
+}
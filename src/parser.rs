@@ -1,26 +1,32 @@
-use crate::prelude::*;
-use anyhow::bail;
-use chrono::{DateTime, FixedOffset, TimeZone};
-use chrono_tz::Asia::Vladivostok;
+use crate::{html::HtmlTable, units::SpeedUnit};
+use chrono::{DateTime, FixedOffset, LocalResult, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
 use lazy_static::lazy_static;
 use regex::Regex;
-use select::{
-    document::Document,
-    node::Find,
-    predicate::{Name, Predicate},
-};
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use std::fmt::{self, Display};
+use thiserror::Error;
+
+/// Timezone of the default station
+pub const STATION_TIMEZONE: Tz = chrono_tz::Asia::Vladivostok;
+
+/// Time format of the default station (eg. `29.10.2022 22:45`)
+pub const STATION_DATE_FORMAT: &str = "%d.%m.%Y %H:%M";
 
 lazy_static! {
     static ref WIND_DIRECTION: Regex = Regex::new("([0-9]{1,3})°").unwrap();
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Direction labels station uses when wind is calm or its direction is variable
+const NO_DIRECTION: [&str; 6] = ["штиль", "переменный", "пер.", "calm", "variable", "var"];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Observation {
     pub time: DateTime<FixedOffset>,
-    pub direction: u16,
+    /// Wind direction (deg.). `None` if wind is calm or direction is variable
+    pub direction: Option<u16>,
     pub avg_speed: f32,
+    pub gust_speed: Option<f32>,
 }
 
 const DIRECTIONS: [(u16, &str, &str); 9] = [
@@ -35,83 +41,342 @@ const DIRECTIONS: [(u16, &str, &str); 9] = [
     (315, "NW", "↘"),
 ];
 
+impl Observation {
+    /// Displays observation with a speed in a given unit
+    pub fn display(&self, unit: SpeedUnit) -> ObservationDisplay<'_> {
+        ObservationDisplay(self, unit)
+    }
+}
+
+pub struct ObservationDisplay<'a>(&'a Observation, SpeedUnit);
+
 impl Display for Observation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let (_, direction_str, direction_marker) = DIRECTIONS
-            .iter()
-            .min_by_key(|d| self.direction.abs_diff(d.0))
-            .unwrap();
+        self.display(SpeedUnit::MetersPerSecond).fmt(f)
+    }
+}
+
+impl<'a> Display for ObservationDisplay<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ObservationDisplay(observation, unit) = self;
         write!(
             f,
-            "{} {:2.1} m/s {:2} {} ({:3}°)",
-            self.time.format("%H:%M"),
-            self.avg_speed,
-            direction_str,
-            direction_marker,
-            self.direction
-        )
-    }
-}
-
-pub fn parse(input: &str) -> Result<Vec<Observation>> {
-    let document = Document::from(input);
-    let selector = Name("table").descendant(Name("tr"));
-    let rows = document.find(selector);
-    let mut result = vec![];
-
-    for row in rows {
-        if row.find(Name("th")).next().is_some() {
-            // skippping header
-            continue;
+            "{} {} ",
+            observation.time.format("%H:%M"),
+            unit.format(observation.avg_speed)
+        )?;
+        match observation.direction {
+            Some(direction) => {
+                let (_, direction_str, direction_marker) = DIRECTIONS
+                    .iter()
+                    .min_by_key(|d| direction.abs_diff(d.0))
+                    .unwrap();
+                write!(
+                    f,
+                    "{:2} {} ({:3}°)",
+                    direction_str, direction_marker, direction
+                )
+            }
+            None if observation.avg_speed == 0. => write!(f, "calm"),
+            None => write!(f, "variable"),
         }
-        let mut columns = row.find(Name("td"));
-        let time = parse_column(&mut columns, vlat_time_parser)?;
-        let direction = parse_column(&mut columns, direction_parser)?;
-        let avg_speed = parse_column(&mut columns, wind_speed_parser)?;
-
-        match (time, direction, avg_speed) {
-            (Some(time), Some(direction), Some(avg_speed)) => result.push(Observation {
-                time,
-                direction,
-                avg_speed,
-            }),
-            _ => bail!("Unable to parse HTML"),
+    }
+}
+
+/// Columns of the station observations table
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Column {
+    Time,
+    Direction,
+    AvgSpeed,
+    GustSpeed,
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Column::Time => "time",
+            Column::Direction => "direction",
+            Column::AvgSpeed => "average speed",
+            Column::GustSpeed => "gust speed",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Problem with a single row of the observations table. Row index is 0-based and doesn't count header rows
+#[derive(Error, Debug, PartialEq)]
+pub enum ParseError {
+    #[error("Row {row}: no {column} column")]
+    MissingColumn { row: usize, column: Column },
+
+    #[error("Row {row}: invalid {column} '{value}'")]
+    InvalidValue {
+        row: usize,
+        column: Column,
+        value: String,
+    },
+
+    #[error("No column with header '{0}'")]
+    UnknownHeader(String),
+
+    #[error("Invalid CSS selector '{0}'")]
+    InvalidSelector(String),
+
+    #[error("Malformed document: {0}")]
+    Malformed(String),
+}
+
+/// Column of a table given by 0-based index or header text
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum ColumnRef {
+    Index(usize),
+    Header(String),
+}
+
+/// Columns of a table observation fields are read from
+#[derive(Deserialize, Clone, Debug)]
+pub struct Columns {
+    pub time: ColumnRef,
+    pub direction: ColumnRef,
+    pub avg_speed: ColumnRef,
+    #[serde(default)]
+    pub gust_speed: Option<ColumnRef>,
+}
+
+/// Layout of the default station table: time, direction, average speed and gust speed
+impl Default for Columns {
+    fn default() -> Self {
+        Self {
+            time: ColumnRef::Index(0),
+            direction: ColumnRef::Index(1),
+            avg_speed: ColumnRef::Index(2),
+            gust_speed: Some(ColumnRef::Index(3)),
         }
     }
-    Ok(result)
 }
 
-fn parse_column<O, I: Predicate>(
-    columns: &mut Find<I>,
-    parser: fn(&str) -> Result<Option<O>>,
-) -> Result<Option<O>> {
-    if let Some(column) = columns.next() {
-        Ok(parser(&column.text())?)
-    } else {
-        bail!("No column left")
+impl Columns {
+    /// Resolves columns to indices. Columns given by header text are searched in `header` (case insensitive)
+    pub fn resolve(&self, header: &[String]) -> Result<Layout, ParseError> {
+        let resolve = |column: &ColumnRef| match column {
+            ColumnRef::Index(idx) => Ok(*idx),
+            ColumnRef::Header(name) => header
+                .iter()
+                .position(|h| h.trim().to_lowercase() == name.trim().to_lowercase())
+                .ok_or_else(|| ParseError::UnknownHeader(name.clone())),
+        };
+        Ok(Layout {
+            time: resolve(&self.time)?,
+            direction: resolve(&self.direction)?,
+            avg_speed: resolve(&self.avg_speed)?,
+            gust_speed: self.gust_speed.as_ref().map(resolve).transpose()?,
+        })
+    }
+
+    /// True if any column is given by header text
+    pub fn needs_header(&self) -> bool {
+        let columns = [&self.time, &self.direction, &self.avg_speed];
+        columns
+            .into_iter()
+            .chain(&self.gust_speed)
+            .any(|c| matches!(c, ColumnRef::Header(_)))
     }
 }
 
-// Parsing the string of format: `СЗЗ (301°)`
-fn direction_parser(input: &str) -> Result<Option<u16>> {
-    if let Some(caps) = WIND_DIRECTION.captures(input) {
-        let direction = caps.get(1).unwrap();
-        let direction = direction.as_str().parse::<u16>()?;
-        if direction <= 360 {
-            return Ok(Some(direction % 360));
+/// Indices of observation fields in a table row
+#[derive(Clone, Copy, Debug)]
+pub struct Layout {
+    time: usize,
+    direction: usize,
+    avg_speed: usize,
+    gust_speed: Option<usize>,
+}
+
+impl Layout {
+    fn index(&self, column: Column) -> Option<usize> {
+        match column {
+            Column::Time => Some(self.time),
+            Column::Direction => Some(self.direction),
+            Column::AvgSpeed => Some(self.avg_speed),
+            Column::GustSpeed => self.gust_speed,
         }
     }
-    Ok(None)
 }
 
-fn wind_speed_parser(input: &str) -> Result<Option<f32>> {
-    Ok(Some(input.parse::<f32>()?))
+/// Local time station reports observations in
+#[derive(Clone, Debug)]
+pub struct StationClock {
+    pub timezone: Tz,
+    /// `strftime`-like format of observation time. `%s` means Unix timestamp (not a local time). Times with UTC
+    /// offset (`%z`, `%:z` or `%+`) are converted to the station timezone
+    pub date_format: String,
 }
 
-// Parse time in format: `29.10.2022 22:45` assuming it's in VLAT
-fn vlat_time_parser(input: &str) -> Result<Option<DateTime<FixedOffset>>> {
-    let time = Vladivostok.datetime_from_str(input, "%d.%m.%Y %H:%M")?;
-    Ok(Some(time.with_timezone(&FixedOffset::east(10 * 3600))))
+impl Default for StationClock {
+    fn default() -> Self {
+        Self {
+            timezone: STATION_TIMEZONE,
+            date_format: STATION_DATE_FORMAT.to_string(),
+        }
+    }
+}
+
+impl StationClock {
+    /// Parses station local time
+    ///
+    /// Returned time has an actual UTC offset of the moment (DST aware). Local time which doesn't exist (skipped
+    /// when clocks go forward) is invalid. Local time happening twice (when clocks go back) is resolved to the
+    /// moment closest to `near` (time of the neighbouring row), so observations stay in order. The earliest one
+    /// is chosen without `near`.
+    pub fn parse(
+        &self,
+        input: &str,
+        near: Option<DateTime<FixedOffset>>,
+    ) -> Option<DateTime<FixedOffset>> {
+        if self.date_format == "%s" {
+            let time = self
+                .timezone
+                .timestamp_opt(input.parse().ok()?, 0)
+                .single()?;
+            return Some(time.with_timezone(&time.offset().fix()));
+        }
+        if let Ok(time) = DateTime::parse_from_str(input, &self.date_format) {
+            let time = time.with_timezone(&self.timezone);
+            return Some(time.with_timezone(&time.offset().fix()));
+        }
+        let local = NaiveDateTime::parse_from_str(input, &self.date_format).ok()?;
+        let time = match self.timezone.from_local_datetime(&local) {
+            LocalResult::None => return None,
+            LocalResult::Single(time) => time,
+            LocalResult::Ambiguous(earliest, latest) => match near {
+                Some(near) => {
+                    let distance =
+                        |t: &DateTime<Tz>| t.signed_duration_since(near).num_seconds().abs();
+                    if distance(&latest) < distance(&earliest) {
+                        latest
+                    } else {
+                        earliest
+                    }
+                }
+                None => earliest,
+            },
+        };
+        Some(time.with_timezone(&time.offset().fix()))
+    }
+}
+
+/// Result of parsing observations page
+///
+/// Rows failed to parse are skipped and reported in `errors`, so single malformed row doesn't discard the whole page.
+#[derive(Debug, Default)]
+pub struct Parsed {
+    pub observations: Vec<Observation>,
+    pub errors: Vec<ParseError>,
+}
+
+/// Parses the default station page
+pub fn parse(input: &str, clock: &StationClock) -> Parsed {
+    HtmlTable::default()
+        .parse(input, clock, SpeedUnit::MetersPerSecond)
+        .expect("Default table layout doesn't depend on headers")
+}
+
+/// Parses table rows (cell texts) with a given layout. Speeds are converted from `unit` to m/s
+pub fn parse_rows(
+    rows: impl IntoIterator<Item = Vec<String>>,
+    layout: &Layout,
+    clock: &StationClock,
+    unit: SpeedUnit,
+) -> Parsed {
+    let mut result = Parsed::default();
+
+    for (row, columns) in rows.into_iter().enumerate() {
+        let previous = result.observations.last().map(|o| o.time);
+        match parse_row(row, &columns, layout, clock, unit, previous) {
+            Ok((observation, gust_error)) => {
+                result.observations.push(observation);
+                result.errors.extend(gust_error);
+            }
+            Err(e) => result.errors.push(e),
+        }
+    }
+    result
+}
+
+/// Parses a row of the table. Invalid gust speed is not fatal: observation is returned without it along with an error
+fn parse_row(
+    row: usize,
+    columns: &[String],
+    layout: &Layout,
+    clock: &StationClock,
+    unit: SpeedUnit,
+    previous: Option<DateTime<FixedOffset>>,
+) -> Result<(Observation, Option<ParseError>), ParseError> {
+    let column = |column: Column| {
+        layout
+            .index(column)
+            .and_then(|idx| columns.get(idx))
+            .map(|value| value.trim())
+            .ok_or(ParseError::MissingColumn { row, column })
+    };
+    let invalid = |column: Column, value: &str| ParseError::InvalidValue {
+        row,
+        column,
+        value: value.to_string(),
+    };
+
+    let time = column(Column::Time)?;
+    let time = clock
+        .parse(time, previous)
+        .ok_or_else(|| invalid(Column::Time, time))?;
+    let direction = column(Column::Direction)?;
+    let direction =
+        direction_parser(direction).ok_or_else(|| invalid(Column::Direction, direction))?;
+    let avg_speed = column(Column::AvgSpeed)?;
+    let avg_speed =
+        wind_speed_parser(avg_speed).ok_or_else(|| invalid(Column::AvgSpeed, avg_speed))?;
+
+    let (gust_speed, gust_error) = match column(Column::GustSpeed) {
+        Ok("") | Err(_) => (None, None),
+        Ok(gust) => match wind_speed_parser(gust) {
+            Some(gust) => (Some(gust), None),
+            None => (None, Some(invalid(Column::GustSpeed, gust))),
+        },
+    };
+
+    let observation = Observation {
+        time,
+        direction,
+        avg_speed: unit.to_ms(avg_speed),
+        gust_speed: gust_speed.map(|gust| unit.to_ms(gust)),
+    };
+    Ok((observation, gust_error))
+}
+
+// Parsing the string of format: `СЗЗ (301°)` or just `301`. Calm or variable wind is parsed as `Some(None)`.
+// Missing direction (empty CSV field or `null` in exported history) is parsed the same way
+fn direction_parser(input: &str) -> Option<Option<u16>> {
+    if input.is_empty() {
+        return Some(None);
+    }
+    let degrees = match WIND_DIRECTION.captures(input) {
+        Some(caps) => caps.get(1).unwrap().as_str().parse::<f32>().ok(),
+        None => input.parse::<f32>().ok(),
+    };
+    if let Some(direction) = degrees {
+        let direction = direction.round();
+        return (0. ..=360.)
+            .contains(&direction)
+            .then_some(Some(direction as u16 % 360));
+    }
+    let input = input.to_lowercase();
+    NO_DIRECTION.contains(&input.as_str()).then_some(None)
+}
+
+fn wind_speed_parser(input: &str) -> Option<f32> {
+    input.parse::<f32>().ok()
 }
 
 #[cfg(test)]
@@ -121,10 +386,92 @@ mod test {
     use insta::assert_yaml_snapshot;
 
     #[test]
-    fn foo() -> Result<()> {
+    fn foo() {
         let input = include_str!("../tests/example.html");
-        assert_yaml_snapshot!(parse(input)?);
+        let parsed = parse(input, &StationClock::default());
+        assert_eq!(Vec::<ParseError>::new(), parsed.errors);
+        assert_yaml_snapshot!(parsed.observations);
+    }
+
+    #[test]
+    fn bad_rows_are_skipped() {
+        let input = r#"<table>
+            <tr><th>Время</th><th>Направление</th><th>Скорость</th><th>Порывы</th></tr>
+            <tr><td>29.10.2022 22:46</td><td>СЗ (318°)</td><td>2.6</td><td>3.7</td></tr>
+            <tr><td>29.10.2022 22:45</td><td>Штиль</td><td>0</td><td></td></tr>
+            <tr><td>29.10.2022 22:44</td><td>переменный</td><td>1.2</td><td>-</td></tr>
+            <tr><td>29.10.2022 22:43</td><td>СЗ (318°)</td><td></td><td>3.7</td></tr>
+            <tr><td>29.10.2022 22:42</td><td>???</td><td>2.6</td><td>3.7</td></tr>
+            <tr><td>29.10.2022 22:41</td></tr>
+        </table>"#;
+        let parsed = parse(input, &StationClock::default());
+
+        let observations = parsed
+            .observations
+            .iter()
+            .map(|o| o.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "22:46 2.6 m/s NW ↘ (318°)",
+                "22:45 0.0 m/s calm",
+                "22:44 1.2 m/s variable"
+            ],
+            observations
+        );
+
+        let invalid = |row, column, value: &str| ParseError::InvalidValue {
+            row,
+            column,
+            value: value.to_string(),
+        };
+        assert_eq!(
+            vec![
+                invalid(2, Column::GustSpeed, "-"),
+                invalid(3, Column::AvgSpeed, ""),
+                invalid(4, Column::Direction, "???"),
+                ParseError::MissingColumn {
+                    row: 5,
+                    column: Column::Direction
+                },
+            ],
+            parsed.errors
+        );
+    }
+
+    #[test]
+    fn daylight_saving_time() {
+        let clock = StationClock {
+            timezone: chrono_tz::Europe::Berlin,
+            date_format: "%Y-%m-%d %H:%M".to_string(),
+        };
+        let time = |s| DateTime::parse_from_rfc3339(s).unwrap();
+
+        let summer = clock.parse("2022-07-01 12:00", None);
+        assert_eq!(Some(time("2022-07-01T12:00:00+02:00")), summer);
+        assert_eq!("+02:00", summer.unwrap().offset().to_string());
+        let winter = clock.parse("2022-12-01 12:00", None);
+        assert_eq!("+01:00", winter.unwrap().offset().to_string());
+
+        // clocks go forward from 02:00 to 03:00
+        assert_eq!(None, clock.parse("2022-03-27 02:30", None));
+
+        // 02:30 happens twice when clocks go back from 03:00 to 02:00
+        let first = time("2022-10-30T02:30:00+02:00");
+        let second = time("2022-10-30T02:30:00+01:00");
+        assert_eq!(Some(first), clock.parse("2022-10-30 02:30", None));
+        let near = time("2022-10-30T02:20:00+01:00");
+        assert_eq!(Some(second), clock.parse("2022-10-30 02:30", Some(near)));
+        let near = time("2022-10-30T02:40:00+02:00");
+        assert_eq!(Some(first), clock.parse("2022-10-30 02:30", Some(near)));
 
-        Ok(())
+        // time with UTC offset is unambiguous
+        let clock = StationClock {
+            date_format: "%+".to_string(),
+            ..clock
+        };
+        let parsed = clock.parse("2022-10-30T01:30:00Z", None).unwrap();
+        assert_eq!(second, parsed);
+        assert_eq!("+01:00", parsed.offset().to_string());
     }
 }
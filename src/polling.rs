@@ -0,0 +1,182 @@
+//! Adaptive polling interval of observation sources
+//!
+//! Stations publish observations with their own cadence (every minute, every 10 minutes...), so polling more often
+//! is pointless and polling less often delays alerts. The cadence is estimated as the median gap between the last
+//! observation times. The interval is shortened while the wind is changing (tracker is in [`WindState::Candidate`]
+//! or [`WindState::Cooldown`]), so alerts are not delayed, and stretched at night and after repeated failures, so
+//! the station is not hammered while nobody needs it or it is down.
+use crate::WindState;
+use chrono::{DateTime, FixedOffset, NaiveTime, Utc};
+use chrono_tz::Tz;
+use std::{collections::VecDeque, time::Duration};
+
+/// Number of the last observation times cadence is estimated from
+const CADENCE_WINDOW: usize = 10;
+
+/// Interval is divided by this factor while the wind is changing
+const TRANSIENT_FACTOR: u32 = 3;
+
+/// Interval is multiplied by this factor at night
+const NIGHT_FACTOR: u32 = 4;
+
+/// Interval is doubled on each consecutive failure up to this number of times
+const MAX_BACKOFF_EXPONENT: u32 = 5;
+
+pub struct Polling {
+    /// Interval used until the cadence is known
+    pub default: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    /// Timezone of the station night is defined in
+    pub timezone: Tz,
+    /// Local night hours (start, end)
+    pub night: (NaiveTime, NaiveTime),
+    state: WindState,
+    failures: u32,
+    /// Distinct observation times in ascending order
+    times: VecDeque<DateTime<FixedOffset>>,
+}
+
+impl Polling {
+    pub fn new(default: Duration, max: Duration, timezone: Tz) -> Self {
+        Self {
+            default,
+            min: Duration::from_secs(10),
+            max,
+            timezone,
+            night: (
+                NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            ),
+            state: WindState::Low,
+            failures: 0,
+            times: VecDeque::new(),
+        }
+    }
+
+    /// Records times of fetched observations and resets failure count
+    pub fn observed(&mut self, times: impl IntoIterator<Item = DateTime<FixedOffset>>) {
+        self.failures = 0;
+        for time in times {
+            if let Err(idx) = self.times.binary_search(&time) {
+                self.times.insert(idx, time);
+            }
+        }
+        while self.times.len() > CADENCE_WINDOW {
+            self.times.pop_front();
+        }
+    }
+
+    pub fn failed(&mut self) {
+        self.failures += 1;
+    }
+
+    /// Records state of the spot tracker
+    pub fn set_state(&mut self, state: WindState) {
+        self.state = state;
+    }
+
+    /// Median gap between the last observations
+    pub fn cadence(&self) -> Option<Duration> {
+        let mut gaps = self
+            .times
+            .iter()
+            .zip(self.times.iter().skip(1))
+            .filter_map(|(a, b)| (*b - *a).to_std().ok())
+            .collect::<Vec<_>>();
+        if gaps.is_empty() {
+            return None;
+        }
+        gaps.sort();
+        Some(gaps[gaps.len() / 2])
+    }
+
+    /// Interval to wait before the next fetch
+    pub fn next(&self, now: DateTime<Utc>) -> Duration {
+        let base = self.cadence().unwrap_or(self.default);
+        let interval = if self.failures > 0 {
+            base * 2u32.pow(self.failures.min(MAX_BACKOFF_EXPONENT))
+        } else {
+            match self.state {
+                WindState::Candidate(_) | WindState::Cooldown(_) => base / TRANSIENT_FACTOR,
+                WindState::Low if self.is_night(now) => base * NIGHT_FACTOR,
+                WindState::Low | WindState::High => base,
+            }
+        };
+        interval.clamp(self.min, self.max)
+    }
+
+    fn is_night(&self, now: DateTime<Utc>) -> bool {
+        let time = now.with_timezone(&self.timezone).time();
+        let (start, end) = self.night;
+        if start <= end {
+            start <= time && time < end
+        } else {
+            time >= start || time < end
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn polling() -> Polling {
+        Polling::new(
+            Duration::from_secs(55),
+            Duration::from_secs(1800),
+            chrono_tz::Asia::Vladivostok,
+        )
+    }
+
+    /// Local time of the station
+    fn time(time: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(&format!("2022-10-29T{time}:00+10:00")).unwrap()
+    }
+
+    fn minutes(minutes: &[i64]) -> Vec<DateTime<FixedOffset>> {
+        minutes
+            .iter()
+            .map(|m| time("12:00") + chrono::Duration::minutes(*m))
+            .collect()
+    }
+
+    #[test]
+    fn interval_follows_cadence() {
+        let noon = time("12:00").into();
+        let mut polling = polling();
+        assert_eq!(None, polling.cadence());
+        assert_eq!(Duration::from_secs(55), polling.next(noon));
+
+        // one late observation doesn't change cadence
+        polling.observed(minutes(&[0, 10, 20, 30, 45]));
+        polling.observed(minutes(&[45, 55]));
+        assert_eq!(Some(Duration::from_secs(600)), polling.cadence());
+        assert_eq!(Duration::from_secs(600), polling.next(noon));
+
+        polling.set_state(WindState::Candidate(1));
+        assert_eq!(Duration::from_secs(200), polling.next(noon));
+        polling.set_state(WindState::High);
+        assert_eq!(Duration::from_secs(600), polling.next(noon));
+    }
+
+    #[test]
+    fn interval_is_stretched_at_night_and_on_failures() {
+        let mut polling = polling();
+        polling.observed(minutes(&[0, 1, 2, 3]));
+
+        let night = time("23:00").into();
+        assert_eq!(Duration::from_secs(240), polling.next(night));
+        polling.set_state(WindState::Cooldown(1));
+        assert_eq!(Duration::from_secs(20), polling.next(night));
+
+        let noon = time("12:00").into();
+        for _ in 0..10 {
+            polling.failed();
+        }
+        assert_eq!(Duration::from_secs(1800), polling.next(noon));
+        polling.observed(vec![]);
+        assert_eq!(Duration::from_secs(20), polling.next(noon));
+    }
+}
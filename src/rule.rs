@@ -0,0 +1,465 @@
+//! Alert rules
+//!
+//! Rule is a boolean expression tested against every [`Observation`]. Rules can be deserialized using serde
+//! or parsed from the text form:
+//!
+//! ```text
+//! avg >= 7 and direction in NE..E and gust <= 14 and weekday
+//! ```
+//!
+//! `and` binds tighter than `or`, `not` negates next term and parenthesis can be used for grouping.
+use crate::{
+    parser::Observation,
+    prelude::*,
+    units::{Speed, SpeedUnit},
+    Sector,
+};
+use anyhow::bail;
+use chrono::{Datelike, Weekday};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    /// All of the rules should match
+    And(Vec<Rule>),
+    /// At least one of the rules should match
+    Or(Vec<Rule>),
+    Not(Box<Rule>),
+    /// Average wind speed
+    AvgSpeed(Comparison),
+    /// Gust speed. Never matches when observation has no gust data
+    Gust(Comparison),
+    /// Wind direction is inside of the sector
+    Direction(Sector),
+    /// Observation is made Monday to Friday (station local time)
+    Weekday,
+    /// Observation is made on Saturday or Sunday (station local time)
+    Weekend,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    pub op: Op,
+    pub value: f32,
+    /// Unit of `value`. Kept as given by user, so the rule is shown back the same way
+    #[serde(default)]
+    pub unit: SpeedUnit,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    #[serde(rename = ">=")]
+    Ge,
+    #[serde(rename = ">")]
+    Gt,
+    #[serde(rename = "<=")]
+    Le,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = "=")]
+    Eq,
+}
+
+impl Rule {
+    /// Rule matching average speed above threshold in a given sector
+    pub fn speed_in_sector(avg_speed_threshold: f32, sector: Sector) -> Self {
+        Rule::And(vec![
+            Rule::AvgSpeed(Comparison {
+                op: Op::Ge,
+                value: avg_speed_threshold,
+                unit: SpeedUnit::MetersPerSecond,
+            }),
+            Rule::Direction(sector),
+        ])
+    }
+
+    pub fn test(&self, observation: &Observation) -> bool {
+        match self {
+            Rule::And(rules) => rules.iter().all(|r| r.test(observation)),
+            Rule::Or(rules) => rules.iter().any(|r| r.test(observation)),
+            Rule::Not(rule) => !rule.test(observation),
+            Rule::AvgSpeed(c) => c.test(observation.avg_speed),
+            Rule::Gust(c) => observation.gust_speed.map(|v| c.test(v)).unwrap_or(false),
+            Rule::Direction(sector) => observation
+                .direction
+                .map(|d| sector.contains(d))
+                .unwrap_or(false),
+            Rule::Weekday => !is_weekend(observation.time.weekday()),
+            Rule::Weekend => is_weekend(observation.time.weekday()),
+        }
+    }
+
+    fn is_compound(&self) -> bool {
+        matches!(self, Rule::And(_) | Rule::Or(_))
+    }
+}
+
+fn is_weekend(day: Weekday) -> bool {
+    matches!(day, Weekday::Sat | Weekday::Sun)
+}
+
+impl Comparison {
+    /// Tests given speed (m/s)
+    pub fn test(&self, value: f32) -> bool {
+        let threshold = self.unit.to_ms(self.value);
+        match self.op {
+            Op::Ge => value >= threshold,
+            Op::Gt => value > threshold,
+            Op::Le => value <= threshold,
+            Op::Lt => value < threshold,
+            Op::Eq => value == threshold,
+        }
+    }
+
+    fn speed(&self) -> Speed {
+        Speed {
+            value: self.value,
+            unit: self.unit,
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Op::Ge => ">=",
+            Op::Gt => ">",
+            Op::Le => "<=",
+            Op::Lt => "<",
+            Op::Eq => "=",
+        };
+        f.write_str(op)
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::And(rules) => {
+                for (i, rule) in rules.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" and ")?;
+                    }
+                    if matches!(rule, Rule::Or(_)) {
+                        write!(f, "({rule})")?;
+                    } else {
+                        write!(f, "{rule}")?;
+                    }
+                }
+                Ok(())
+            }
+            Rule::Or(rules) => {
+                for (i, rule) in rules.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" or ")?;
+                    }
+                    write!(f, "{rule}")?;
+                }
+                Ok(())
+            }
+            Rule::Not(rule) if rule.is_compound() => write!(f, "not ({rule})"),
+            Rule::Not(rule) => write!(f, "not {rule}"),
+            Rule::AvgSpeed(c) => write!(f, "avg {} {}", c.op, c.speed()),
+            Rule::Gust(c) => write!(f, "gust {} {}", c.op, c.speed()),
+            Rule::Direction(sector) => write!(f, "direction in {sector}"),
+            Rule::Weekday => f.write_str("weekday"),
+            Rule::Weekend => f.write_str("weekend"),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let mut parser = RuleParser {
+            tokens: tokenize(input),
+            pos: 0,
+        };
+        let rule = parser.parse_or()?;
+        if let Some(token) = parser.next() {
+            bail!("Unexpected {} in rule", token);
+        }
+        Ok(rule)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{word}'"),
+            Token::Op(op) => write!(f, "'{op}'"),
+            Token::Open => f.write_str("'('"),
+            Token::Close => f.write_str("')'"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let is_special = |c: char| c.is_whitespace() || "()<>=≥≤".contains(c);
+
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '≥' => Token::Op(Op::Ge),
+            '≤' => Token::Op(Op::Le),
+            '=' => {
+                chars.next_if_eq(&'=');
+                Token::Op(Op::Eq)
+            }
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            c => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| !is_special(*c)) {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+struct RuleParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl RuleParser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn next_keyword_is(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_word(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Word(w)) => Ok(w),
+            Some(token) => bail!("Expected value, got {}", token),
+            None => bail!("Unexpected end of rule"),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Rule> {
+        let mut rules = vec![self.parse_and()?];
+        while self.next_keyword_is("or") {
+            rules.push(self.parse_and()?);
+        }
+        Ok(flatten(rules, Rule::Or))
+    }
+
+    fn parse_and(&mut self) -> Result<Rule> {
+        let mut rules = vec![self.parse_unary()?];
+        while self.next_keyword_is("and") {
+            rules.push(self.parse_unary()?);
+        }
+        Ok(flatten(rules, Rule::And))
+    }
+
+    fn parse_unary(&mut self) -> Result<Rule> {
+        if self.next_keyword_is("not") {
+            return Ok(Rule::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.tokens.get(self.pos) == Some(&Token::Open) {
+            self.pos += 1;
+            let rule = self.parse_or()?;
+            return match self.next() {
+                Some(Token::Close) => Ok(rule),
+                Some(token) => bail!("Expected ')', got {}", token),
+                None => bail!("Unclosed '(' in rule"),
+            };
+        }
+        self.parse_condition()
+    }
+
+    fn parse_condition(&mut self) -> Result<Rule> {
+        let word = self.expect_word()?;
+        match word.to_lowercase().as_str() {
+            "avg" | "speed" => Ok(Rule::AvgSpeed(self.parse_comparison()?)),
+            "gust" => Ok(Rule::Gust(self.parse_comparison()?)),
+            "direction" | "dir" => {
+                if !self.next_keyword_is("in") {
+                    bail!("Expected 'in' after '{}'", word);
+                }
+                Ok(Rule::Direction(self.expect_word()?.parse()?))
+            }
+            "weekday" => Ok(Rule::Weekday),
+            "weekend" => Ok(Rule::Weekend),
+            _ => bail!("Unknown condition '{}'", word),
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Comparison> {
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            Some(token) => bail!("Expected comparison operator, got {}", token),
+            None => bail!("Unexpected end of rule"),
+        };
+        let mut speed: Speed = self.expect_word()?.parse()?;
+        // unit may be given as a separate word: `avg >= 15 kn`
+        if let Some(Token::Word(word)) = self.tokens.get(self.pos) {
+            if let Ok(unit) = word.parse::<SpeedUnit>() {
+                speed.unit = unit;
+                self.pos += 1;
+            }
+        }
+        Ok(Comparison {
+            op,
+            value: speed.value,
+            unit: speed.unit,
+        })
+    }
+}
+
+fn flatten(mut rules: Vec<Rule>, f: fn(Vec<Rule>) -> Rule) -> Rule {
+    if rules.len() == 1 {
+        rules.remove(0)
+    } else {
+        f(rules)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use chrono::DateTime;
+
+    fn observation(
+        time: &str,
+        avg_speed: f32,
+        gust_speed: Option<f32>,
+        direction: u16,
+    ) -> Observation {
+        Observation {
+            time: DateTime::parse_from_rfc3339(time).unwrap(),
+            direction: Some(direction),
+            avg_speed,
+            gust_speed,
+        }
+    }
+
+    #[test]
+    fn parse_rule() -> Result<()> {
+        let rule: Rule = "avg >= 7 AND direction in NE..E and gust <= 14 and weekday".parse()?;
+        assert_eq!(
+            rule,
+            Rule::And(vec![
+                Rule::AvgSpeed(Comparison {
+                    op: Op::Ge,
+                    value: 7.,
+                    unit: SpeedUnit::MetersPerSecond,
+                }),
+                Rule::Direction(Sector::new(45, 90)),
+                Rule::Gust(Comparison {
+                    op: Op::Le,
+                    value: 14.,
+                    unit: SpeedUnit::MetersPerSecond,
+                }),
+                Rule::Weekday,
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rule_display_roundtrip() -> Result<()> {
+        let input = "avg > 5 and (direction in N..E or direction in 190..260) and not (weekend or gust >= 15)";
+        let rule: Rule = input.parse()?;
+        assert_eq!(input, rule.to_string());
+        assert_eq!(rule, rule.to_string().parse()?);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!("avg >=".parse::<Rule>().is_err());
+        assert!("avg >= fast".parse::<Rule>().is_err());
+        assert!("avg >= 15 furlongs".parse::<Rule>().is_err());
+        assert!("(avg >= 5".parse::<Rule>().is_err());
+        assert!("avg >= 5 gust".parse::<Rule>().is_err());
+        assert!("direction NE..E".parse::<Rule>().is_err());
+        assert!("temperature > 20".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn rule_test() -> Result<()> {
+        let rule: Rule = "avg >= 7 and direction in NE..E and gust <= 14 and weekday".parse()?;
+
+        // 2022-10-28 is friday
+        assert!(rule.test(&observation("2022-10-28T12:00:00+10:00", 8., Some(12.), 60)));
+        // too gusty
+        assert!(!rule.test(&observation("2022-10-28T12:00:00+10:00", 8., Some(15.), 60)));
+        // no gust data
+        assert!(!rule.test(&observation("2022-10-28T12:00:00+10:00", 8., None, 60)));
+        // saturday
+        assert!(!rule.test(&observation("2022-10-29T12:00:00+10:00", 8., Some(12.), 60)));
+        // wrong direction
+        assert!(!rule.test(&observation(
+            "2022-10-28T12:00:00+10:00",
+            8.,
+            Some(12.),
+            120
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn rule_with_units() -> Result<()> {
+        let rule: Rule = "avg >= 15 kn and gust < 40km/h".parse()?;
+        assert_eq!("avg >= 15kn and gust < 40km/h", rule.to_string());
+
+        assert!(rule.test(&observation("2022-10-28T12:00:00+10:00", 8., Some(10.), 60)));
+        assert!(!rule.test(&observation("2022-10-28T12:00:00+10:00", 7., Some(10.), 60)));
+        assert!(!rule.test(&observation(
+            "2022-10-28T12:00:00+10:00",
+            8.,
+            Some(11.2),
+            60
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn rule_serde() -> Result<()> {
+        let rule: Rule = serde_json::from_str(
+            r#"{"and": [{"avg_speed": {"op": ">=", "value": 7}}, {"direction": "NE..E"}, "weekend"]}"#,
+        )?;
+        assert_eq!(
+            rule,
+            "avg >= 7 and direction in 45..90 and weekend".parse()?
+        );
+        Ok(())
+    }
+}
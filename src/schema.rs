@@ -1,9 +1,76 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    alerts (id) {
+        id -> Integer,
+        session_id -> Integer,
+        chat_id -> BigInt,
+        message_id -> Integer,
+        text -> Text,
+    }
+}
+
+diesel::table! {
+    check_ins (id) {
+        id -> Integer,
+        session_id -> Integer,
+        user_id -> BigInt,
+        name -> Text,
+        status -> Text,
+        updated_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    forecasts (id) {
+        id -> Integer,
+        source -> Text,
+        time -> BigInt,
+        utc_offset -> Integer,
+        fetched_at -> BigInt,
+        direction -> Nullable<Integer>,
+        avg_speed -> Float,
+        gust_speed -> Nullable<Float>,
+    }
+}
+
+diesel::table! {
+    observations (id) {
+        id -> Integer,
+        source -> Text,
+        time -> BigInt,
+        utc_offset -> Integer,
+        direction -> Nullable<Integer>,
+        avg_speed -> Float,
+        gust_speed -> Nullable<Float>,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Integer,
+        source -> Text,
+        started_at -> BigInt,
+        ended_at -> BigInt,
+        utc_offset -> Integer,
+        peak_speed -> Float,
+        avg_speed -> Float,
+        direction -> Nullable<Integer>,
+        observations -> Integer,
+    }
+}
+
 diesel::table! {
     subscriptions (id) {
         id -> Integer,
         user_id -> BigInt,
         created_at -> BigInt,
+        rule -> Nullable<Text>,
+        threshold -> Nullable<Float>,
+        speed_unit -> Nullable<Text>,
+        digest_at -> Nullable<Text>,
+        forecast_at -> Nullable<Text>,
     }
 }
+
+diesel::allow_tables_to_appear_in_same_query!(observations, subscriptions,);
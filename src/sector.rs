@@ -0,0 +1,303 @@
+use crate::prelude::*;
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// 16-point compass names (english, russian) clockwise starting from north
+const COMPASS_POINTS: [(&str, &str); 16] = [
+    ("N", "С"),
+    ("NNE", "ССВ"),
+    ("NE", "СВ"),
+    ("ENE", "ВСВ"),
+    ("E", "В"),
+    ("ESE", "ВЮВ"),
+    ("SE", "ЮВ"),
+    ("SSE", "ЮЮВ"),
+    ("S", "Ю"),
+    ("SSW", "ЮЮЗ"),
+    ("SW", "ЮЗ"),
+    ("WSW", "ЗЮЗ"),
+    ("W", "З"),
+    ("WNW", "ЗСЗ"),
+    ("NW", "СЗ"),
+    ("NNW", "ССЗ"),
+];
+
+/// Non standard russian names used by some anemometer sites (eg. `СЗЗ` instead of `ЗСЗ`)
+const COMPASS_POINTS_ALIASES: [(&str, usize); 4] =
+    [("СВВ", 3), ("ЮВВ", 5), ("ЮЗЗ", 11), ("СЗЗ", 13)];
+
+/// Circle arc given as two angles in clockwise order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CircleArc(u16, u16);
+
+impl CircleArc {
+    pub fn new(from: u16, to: u16) -> Self {
+        Self(from % 360, to % 360)
+    }
+
+    pub fn contains(&self, angle: u16) -> bool {
+        let angle = angle % 360;
+        if self.0 <= self.1 {
+            self.0 <= angle && angle <= self.1
+        } else {
+            self.0 <= angle || angle <= self.1
+        }
+    }
+}
+
+/// Circle sector
+///
+/// Can test if given angle (0-359 deg.) is in circle sector.
+/// Sector is defined as two angles (from angle and to angle). Two angles
+/// always given in clockwise order, so `Sector::new(270, 90)` is upper half circle and
+/// `Sector::new(90, 270)` is lower. Sector also can be a union of several arcs.
+///
+/// Sector can be parsed from a string:
+/// - single compass point: `NE`, `ССВ` (8-point names are 45° wide, 16-point names are 22.5° wide);
+/// - range of compass points or angles: `SE..SW`, `N-E`, `120-210`;
+/// - comma separated list of above: `N..E, SW`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct Sector(Cow<'static, [CircleArc]>);
+
+impl Sector {
+    #[allow(dead_code)]
+    pub const NORTH_180: Sector = Sector(Cow::Borrowed(&[CircleArc(270, 90)]));
+
+    #[allow(dead_code)]
+    pub const SOUTH_180: Sector = Sector(Cow::Borrowed(&[CircleArc(90, 270)]));
+
+    #[allow(dead_code)]
+    pub const EAST_180: Sector = Sector(Cow::Borrowed(&[CircleArc(0, 180)]));
+
+    #[allow(dead_code)]
+    pub const WEST_180: Sector = Sector(Cow::Borrowed(&[CircleArc(180, 0)]));
+
+    #[allow(dead_code)]
+    pub const NORTH_90: Sector = Sector(Cow::Borrowed(&[CircleArc(315, 45)]));
+
+    #[allow(dead_code)]
+    pub const EAST_90: Sector = Sector(Cow::Borrowed(&[CircleArc(45, 135)]));
+
+    #[allow(dead_code)]
+    pub const SOUTH_90: Sector = Sector(Cow::Borrowed(&[CircleArc(135, 225)]));
+
+    #[allow(dead_code)]
+    pub const WEST_90: Sector = Sector(Cow::Borrowed(&[CircleArc(225, 315)]));
+
+    pub fn new(from: u16, to: u16) -> Self {
+        Self::union([CircleArc::new(from, to)])
+    }
+
+    pub fn union(arcs: impl IntoIterator<Item = CircleArc>) -> Self {
+        Self(Cow::Owned(arcs.into_iter().collect()))
+    }
+
+    pub fn arcs(&self) -> &[CircleArc] {
+        &self.0
+    }
+
+    pub fn contains(&self, angle: u16) -> bool {
+        self.0.iter().any(|arc| arc.contains(angle))
+    }
+}
+
+/// Index of 16-point compass point closest to a given angle (0 is north, 4 is east and so on)
+pub fn compass_point_index(angle: u16) -> usize {
+    (angle as f32 / 22.5).round() as usize % COMPASS_POINTS.len()
+}
+
+/// English name of 16-point compass point closest to a given angle
+pub fn compass_point_name(angle: u16) -> &'static str {
+    COMPASS_POINTS[compass_point_index(angle)].0
+}
+
+/// Returns angle (deg.) and width of a sector (deg.) given compass point denotes
+fn parse_compass_point(name: &str) -> Option<(f32, f32)> {
+    let name = name.to_uppercase();
+    let index = COMPASS_POINTS
+        .iter()
+        .position(|(en, ru)| *en == name || *ru == name)
+        .or_else(|| {
+            COMPASS_POINTS_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == name)
+                .map(|(_, idx)| *idx)
+        })?;
+    let width = if index % 2 == 0 { 45. } else { 22.5 };
+    Some((index as f32 * 22.5, width))
+}
+
+fn parse_angle(input: &str) -> Result<u16> {
+    let input = input.trim().trim_end_matches('°');
+    if let Some((angle, _)) = parse_compass_point(input) {
+        return Ok(angle.round() as u16 % 360);
+    }
+    match input.parse::<u16>() {
+        Ok(angle) if angle <= 360 => Ok(angle % 360),
+        _ => bail!("Invalid direction '{}'", input),
+    }
+}
+
+fn parse_arc(input: &str) -> Result<CircleArc> {
+    let input = input.trim();
+    let range = input.split_once("..").or_else(|| input.split_once('-'));
+    if let Some((from, to)) = range {
+        return Ok(CircleArc::new(parse_angle(from)?, parse_angle(to)?));
+    }
+    let (angle, width) =
+        parse_compass_point(input).ok_or_else(|| anyhow!("Invalid sector '{}'", input))?;
+    let from = (angle - width / 2.).rem_euclid(360.).round() as u16;
+    let to = (angle + width / 2.).rem_euclid(360.).round() as u16;
+    Ok(CircleArc::new(from, to))
+}
+
+impl FromStr for Sector {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let arcs = input
+            .split(',')
+            .map(parse_arc)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::union(arcs))
+    }
+}
+
+impl TryFrom<String> for Sector {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Sector> for String {
+    fn from(sector: Sector) -> Self {
+        sector.to_string()
+    }
+}
+
+impl Display for CircleArc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Only 8-point compass names are used, so the sector can be represented exactly
+        let name = |angle: u16| {
+            (0..8)
+                .find(|i| i * 45 == angle)
+                .map(|i| COMPASS_POINTS[i as usize * 2].0)
+        };
+        match (name(self.0), name(self.1)) {
+            (Some(from), Some(to)) => write!(f, "{}..{}", from, to),
+            _ => write!(f, "{}..{}", self.0, self.1),
+        }
+    }
+}
+
+impl Display for Sector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, arc) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", arc)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
+    fn sector() {
+        let sector = Sector::new(0, 45);
+
+        assert_eq!(true, sector.contains(0));
+        assert_eq!(true, sector.contains(30));
+        assert_eq!(true, sector.contains(45));
+
+        assert_eq!(false, sector.contains(46));
+        assert_eq!(false, sector.contains(359));
+
+        let sector = Sector::new(280, 90);
+
+        assert_eq!(true, sector.contains(290));
+        assert_eq!(true, sector.contains(0));
+        assert_eq!(true, sector.contains(45));
+        assert_eq!(true, sector.contains(90));
+
+        assert_eq!(false, sector.contains(180));
+        assert_eq!(false, sector.contains(279));
+    }
+
+    #[test]
+    fn multi_range_sector() {
+        let sector = Sector::union([CircleArc::new(350, 10), CircleArc::new(170, 190)]);
+
+        assert!(sector.contains(0));
+        assert!(sector.contains(180));
+
+        assert!(!sector.contains(90));
+        assert!(!sector.contains(270));
+    }
+
+    #[test]
+    fn parse_sector() -> Result<()> {
+        assert_eq!(Sector::new(23, 68), "NE".parse()?);
+        assert_eq!(Sector::new(338, 23), "n".parse()?);
+        assert_eq!(Sector::new(0, 90), "N-E".parse()?);
+        assert_eq!(Sector::new(135, 225), "SE..SW".parse()?);
+        assert_eq!(Sector::new(120, 210), "120-210".parse()?);
+        assert_eq!(Sector::new(120, 210), "120°..210°".parse()?);
+        assert_eq!(Sector::new(11, 34), "NNE".parse()?);
+        assert_eq!(Sector::new(281, 304), "СЗЗ".parse()?);
+        assert_eq!(Sector::new(281, 304), "ЗСЗ".parse()?);
+        assert_eq!(Sector::new(315, 45), "СЗ..СВ".parse()?);
+        assert_eq!(
+            Sector::union([CircleArc::new(0, 90), CircleArc::new(180, 270)]),
+            "N..E, S..W".parse()?
+        );
+
+        assert!("".parse::<Sector>().is_err());
+        assert!("NEE".parse::<Sector>().is_err());
+        assert!("120".parse::<Sector>().is_err());
+        assert!("120-400".parse::<Sector>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sector_display_roundtrip() -> Result<()> {
+        for input in ["SE..SW", "120..210", "N..E,S..W", "NW..NE"] {
+            let sector: Sector = input.parse()?;
+            assert_eq!(input, sector.to_string());
+        }
+        assert_eq!("NW..NE", Sector::NORTH_90.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn compass_point_names() {
+        assert_eq!("N", compass_point_name(0));
+        assert_eq!("N", compass_point_name(355));
+        assert_eq!("NNE", compass_point_name(20));
+        assert_eq!("WNW", compass_point_name(301));
+        assert_eq!(4, compass_point_index(90));
+    }
+
+    #[test]
+    fn sector_serde() -> Result<()> {
+        let sector: Sector = serde_json::from_str(r#""SE..SW""#)?;
+        assert_eq!(Sector::SOUTH_90, sector);
+        assert_eq!(r#""SE..SW""#, serde_json::to_string(&sector)?);
+        assert!(serde_json::from_str::<Sector>(r#""nowhere""#).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+//! Sessions: periods of good wind at a spot
+//!
+//! Session starts when the spot tracker reaches [`WindState::High`] and ends when it's back to [`WindState::Low`]
+//! (cooldown observations are part of the session). Sessions are saved when they start and updated with every
+//! observation, so a session interrupted by restart ends with the last observation processed.
+use crate::{
+    compass_point_index, compass_point_name, digest::most_frequent_point, parser::Observation,
+    units::SpeedUnit, WindState,
+};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt::Write};
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Session {
+    pub start: DateTime<FixedOffset>,
+    /// Time of the observation wind dropped at (the last observation while session is in progress)
+    pub end: DateTime<FixedOffset>,
+    /// Maximum average speed (m/s)
+    pub peak_speed: f32,
+    pub avg_speed: f32,
+    /// Direction (16-point compass point angle) wind was blowing from most of the session
+    pub direction: Option<u16>,
+    pub observations: u32,
+}
+
+impl Session {
+    pub fn format(&self, unit: SpeedUnit) -> String {
+        let minutes = (self.end - self.start).num_minutes();
+        let mut result = format!(
+            "{} {}–{} ({}h {:02}m) max {}, average {}",
+            self.start.format("%d.%m"),
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M"),
+            minutes / 60,
+            minutes % 60,
+            unit.format(self.peak_speed),
+            unit.format(self.avg_speed),
+        );
+        if let Some(direction) = self.direction {
+            write!(result, ", {}", compass_point_name(direction)).unwrap();
+        }
+        result
+    }
+}
+
+/// Session in progress
+struct Current {
+    start: DateTime<FixedOffset>,
+    end: DateTime<FixedOffset>,
+    peak_speed: f32,
+    total_speed: f32,
+    directions: [u32; 16],
+    observations: u32,
+}
+
+impl Current {
+    fn new(observation: &Observation) -> Self {
+        let mut current = Self {
+            start: observation.time,
+            end: observation.time,
+            peak_speed: 0.,
+            total_speed: 0.,
+            directions: [0; 16],
+            observations: 0,
+        };
+        current.add(observation);
+        current
+    }
+
+    fn add(&mut self, observation: &Observation) {
+        self.end = observation.time;
+        self.peak_speed = self.peak_speed.max(observation.avg_speed);
+        self.total_speed += observation.avg_speed;
+        if let Some(direction) = observation.direction {
+            self.directions[compass_point_index(direction)] += 1;
+        }
+        self.observations += 1;
+    }
+
+    fn session(&self) -> Session {
+        Session {
+            start: self.start,
+            end: self.end,
+            peak_speed: self.peak_speed,
+            avg_speed: self.total_speed / self.observations as f32,
+            direction: most_frequent_point(&self.directions),
+            observations: self.observations,
+        }
+    }
+}
+
+/// Change of the session caused by an observation
+#[derive(Debug, PartialEq)]
+pub enum SessionEvent {
+    Started(Session),
+    Updated(Session),
+    Ended(Session),
+}
+
+/// Builds sessions from observations and states of the spot tracker
+#[derive(Default)]
+pub struct SessionTracker {
+    current: Option<Current>,
+}
+
+impl SessionTracker {
+    /// Processes observation given tracker state after the observation
+    pub fn step(&mut self, observation: &Observation, state: WindState) -> Option<SessionEvent> {
+        match (&mut self.current, state) {
+            (None, WindState::High) => {
+                let current = Current::new(observation);
+                let session = current.session();
+                self.current = Some(current);
+                Some(SessionEvent::Started(session))
+            }
+            (None, _) => None,
+            (Some(current), WindState::High | WindState::Cooldown(_)) => {
+                current.add(observation);
+                Some(SessionEvent::Updated(current.session()))
+            }
+            (Some(current), WindState::Low | WindState::Candidate(_)) => {
+                current.end = observation.time;
+                let session = current.session();
+                self.current = None;
+                Some(SessionEvent::Ended(session))
+            }
+        }
+    }
+}
+
+/// Days having at least one session and number of sessions by month
+pub fn good_days_by_month(sessions: &[Session]) -> BTreeMap<NaiveDate, (usize, usize)> {
+    let mut days = BTreeMap::<NaiveDate, Vec<NaiveDate>>::new();
+    for session in sessions {
+        let date = session.start.date_naive();
+        let month = date.with_day(1).unwrap();
+        days.entry(month).or_default().push(date);
+    }
+    days.into_iter()
+        .map(|(month, mut dates)| {
+            let sessions = dates.len();
+            dates.dedup();
+            (month, (dates.len(), sessions))
+        })
+        .collect()
+}
+
+/// Report of sessions ordered by start time
+pub fn format_sessions(spot: &str, days: i64, sessions: &[Session], unit: SpeedUnit) -> String {
+    if sessions.is_empty() {
+        return format!("No sessions at {} in the last {} days", spot, days);
+    }
+    let mut result = format!("Sessions at {} in the last {} days:\n", spot, days);
+    for session in sessions {
+        writeln!(result, "{}", session.format(unit)).unwrap();
+    }
+    result.push_str("\nGood days by month:");
+    for (month, (days, sessions)) in good_days_by_month(sessions) {
+        write!(
+            result,
+            "\n{}: {} day(s), {} session(s)",
+            month.format("%m.%Y"),
+            days,
+            sessions
+        )
+        .unwrap();
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn observation(time: &str, direction: u16, avg_speed: f32) -> Observation {
+        Observation {
+            time: DateTime::parse_from_rfc3339(&format!("2022-10-{time}:00+10:00")).unwrap(),
+            direction: Some(direction),
+            avg_speed,
+            gust_speed: None,
+        }
+    }
+
+    #[test]
+    fn session_lifecycle() {
+        use WindState::*;
+
+        let mut tracker = SessionTracker::default();
+        assert_eq!(
+            None,
+            tracker.step(&observation("29T11:50", 0, 6.), Candidate(1))
+        );
+        let Some(SessionEvent::Started(session)) =
+            tracker.step(&observation("29T12:00", 0, 6.), High)
+        else {
+            panic!("Session should start");
+        };
+        assert_eq!(1, session.observations);
+        tracker.step(&observation("29T12:10", 315, 9.), High);
+        tracker.step(&observation("29T12:20", 315, 3.), Cooldown(1));
+        let Some(SessionEvent::Ended(session)) =
+            tracker.step(&observation("29T13:40", 315, 2.), Low)
+        else {
+            panic!("Session should end");
+        };
+        assert_eq!(3, session.observations);
+        assert_eq!(6., session.avg_speed);
+        assert_eq!(
+            "29.10 12:00–13:40 (1h 40m) max 9.0 m/s, average 6.0 m/s, NW",
+            session.format(SpeedUnit::MetersPerSecond)
+        );
+        assert_eq!(None, tracker.step(&observation("29T13:50", 0, 2.), Low));
+    }
+
+    /// Subscriber's rule may match when spot's one doesn't. Such alert has no session to check in to
+    #[test]
+    fn alert_outside_session() {
+        use crate::{rule::Rule, Sector, WindTracker};
+
+        let mut spot = WindTracker::new(Rule::speed_in_sector(7., Sector::NORTH_180), 0, 0);
+        let mut subscriber = WindTracker::new(Rule::speed_in_sector(2., Sector::NORTH_180), 0, 0);
+        let mut sessions = SessionTracker::default();
+
+        let obs = observation("29T12:00", 0, 5.);
+        assert!(subscriber.step(&obs));
+        assert!(!spot.step(&obs));
+        assert_eq!(None, sessions.step(&obs, spot.state()));
+    }
+
+    #[test]
+    fn good_days() {
+        let session = |start: &str| Session {
+            start: DateTime::parse_from_rfc3339(start).unwrap(),
+            end: DateTime::parse_from_rfc3339(start).unwrap(),
+            peak_speed: 8.,
+            avg_speed: 7.,
+            direction: None,
+            observations: 1,
+        };
+        let sessions = [
+            session("2022-10-29T10:00:00+10:00"),
+            session("2022-10-29T16:00:00+10:00"),
+            session("2022-10-30T12:00:00+10:00"),
+            session("2022-11-01T12:00:00+10:00"),
+        ];
+        let report = format_sessions("rvs", 30, &sessions, SpeedUnit::Knots);
+        assert!(report.ends_with(
+            "Good days by month:\n10.2022: 2 day(s), 3 session(s)\n11.2022: 1 day(s), 1 session(s)"
+        ));
+    }
+}
@@ -0,0 +1,171 @@
+//! Observation sources of spots
+//!
+//! Sources are declared in a TOML file keyed by spot name, so new stations can be added without code changes:
+//!
+//! ```toml
+//! [rvs]
+//! url = "http://3volna.ru/anemometer/getwind?id=1"
+//! format = "html"
+//!
+//! [other]
+//! url = "https://example.com/wind"
+//! format = "html"
+//! selector = "table#wind tr"
+//! timezone = "Europe/Moscow"
+//! date_format = "%Y-%m-%d %H:%M"
+//! unit = "kn"
+//! columns = { time = "Дата", direction = 2, avg_speed = "Скорость", gust_speed = 1 }
+//!
+//! [holfuy]
+//! url = "https://api.holfuy.com/live/?s=101&m=JSON"
+//! format = "json"
+//! date_format = "%Y-%m-%d %H:%M:%S"
+//! fields = { time = "dateTime", direction = "wind.direction", avg_speed = "wind.speed", gust_speed = "wind.gust" }
+//!
+//! [log]
+//! url = "https://example.com/wind.csv"
+//! format = "csv"
+//! delimiter = ";"
+//! date_format = "%s"
+//! columns = { time = "timestamp", direction = "dir", avg_speed = "avg", gust_speed = "max" }
+//! ```
+//!
+//! `timezone`, `date_format` and `unit` default to the ones of the default station (`Asia/Vladivostok`,
+//! `%d.%m.%Y %H:%M` and `m/s`).
+use crate::{
+    csv_log::CsvLog,
+    html::HtmlTable,
+    json_feed::JsonFeed,
+    parser::{ParseError, Parsed, StationClock, STATION_DATE_FORMAT, STATION_TIMEZONE},
+    prelude::*,
+    units::SpeedUnit,
+};
+use anyhow::Context;
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Source {
+    /// HTTP(S) URL, local file or directory (see [`crate::fetch`])
+    pub url: String,
+    #[serde(default = "default_timezone")]
+    pub timezone: Tz,
+    /// Format of observation time (see `chrono::format::strftime`)
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    /// Unit speeds are given in
+    #[serde(default)]
+    pub unit: SpeedUnit,
+    #[serde(flatten)]
+    pub format: Format,
+}
+
+/// Format of the data source publishes
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum Format {
+    Html(HtmlTable),
+    Json(JsonFeed),
+    Csv(CsvLog),
+}
+
+fn default_timezone() -> Tz {
+    STATION_TIMEZONE
+}
+
+fn default_date_format() -> String {
+    STATION_DATE_FORMAT.to_string()
+}
+
+impl Source {
+    /// Source of the default station layout
+    pub fn html(url: &str, clock: StationClock) -> Self {
+        Self {
+            url: url.to_string(),
+            timezone: clock.timezone,
+            date_format: clock.date_format,
+            unit: SpeedUnit::MetersPerSecond,
+            format: Format::Html(HtmlTable::default()),
+        }
+    }
+
+    pub fn clock(&self) -> StationClock {
+        StationClock {
+            timezone: self.timezone,
+            date_format: self.date_format.clone(),
+        }
+    }
+
+    /// Parses observations published by the source
+    pub fn parse(&self, input: &str) -> std::result::Result<Parsed, ParseError> {
+        match &self.format {
+            Format::Html(table) => table.parse(input, &self.clock(), self.unit),
+            Format::Json(feed) => feed.parse(input, &self.clock(), self.unit),
+            Format::Csv(log) => log.parse(input, &self.clock(), self.unit),
+        }
+    }
+}
+
+/// Reads sources of spots from a TOML file
+pub fn load_sources(path: &Path) -> Result<HashMap<String, Source>> {
+    let path_str = path.display().to_string();
+    let config = fs::read_to_string(path).context(ReadingSources(path_str.clone()))?;
+    let sources = toml::from_str(&config).context(ReadingSources(path_str))?;
+    Ok(sources)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::parser::ColumnRef;
+
+    #[test]
+    fn sources_config() -> Result<()> {
+        let config = r#"
+            [rvs]
+            url = "http://3volna.ru/anemometer/getwind?id=1"
+            format = "html"
+
+            [other]
+            url = "https://example.com/wind"
+            format = "html"
+            selector = "table#wind tr"
+            timezone = "Europe/Moscow"
+            unit = "kn"
+            columns = { time = "Дата", direction = 2, avg_speed = "Скорость" }
+
+            [log]
+            url = "https://example.com/wind.csv"
+            format = "csv"
+            delimiter = ";"
+            date_format = "%s"
+        "#;
+        let sources: HashMap<String, Source> = toml::from_str(config)?;
+
+        let rvs = &sources["rvs"];
+        assert_eq!(STATION_TIMEZONE, rvs.timezone);
+        assert_eq!(STATION_DATE_FORMAT, rvs.date_format);
+        assert_eq!(SpeedUnit::MetersPerSecond, rvs.unit);
+
+        let other = &sources["other"];
+        assert_eq!(chrono_tz::Europe::Moscow, other.timezone);
+        assert_eq!(SpeedUnit::Knots, other.unit);
+        let Format::Html(table) = &other.format else {
+            panic!("HTML source expected");
+        };
+        assert_eq!("table#wind tr", table.selector);
+        assert_eq!(ColumnRef::Header("Дата".to_string()), table.columns.time);
+        assert_eq!(ColumnRef::Index(2), table.columns.direction);
+        assert_eq!(None, table.columns.gust_speed);
+
+        let Format::Csv(log) = &sources["log"].format else {
+            panic!("CSV source expected");
+        };
+        assert_eq!(';', log.delimiter);
+        assert!(log.has_header);
+
+        Ok(())
+    }
+}
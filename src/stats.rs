@@ -0,0 +1,267 @@
+//! Wind statistics of stored history
+//!
+//! - wind rose: share of observations by 16-point compass direction and speed;
+//! - climatology: share of rideable hours by month;
+//! - best time of day: share of rideable hours by hour of the day.
+//!
+//! An hour is rideable if at least half of its observations match the rule. Hours and months are taken in station
+//! local time (the UTC offset observations are stored with).
+use crate::{
+    chart, compass_point_index, compass_point_name, parser::Observation, prelude::*, rule::Rule,
+    units::SpeedUnit,
+};
+use chrono::{Datelike, NaiveDate, Timelike};
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Upper bounds (m/s) of wind rose speed bins (Beaufort forces 3-6). The last bin is open
+pub const SPEED_BINS: [f32; 4] = [3.4, 5.5, 8.0, 10.8];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Number of observations by direction and speed
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct WindRose {
+    /// Counts of speed bins for each 16-point compass direction (0 is north, 4 is east and so on)
+    pub counts: [[u32; SPEED_BINS.len() + 1]; 16],
+    /// Observations without direction
+    pub calm: u32,
+    pub total: u32,
+}
+
+impl WindRose {
+    pub fn new(observations: &[Observation]) -> Self {
+        let mut rose = Self::default();
+        for observation in observations {
+            rose.total += 1;
+            match observation.direction {
+                Some(direction) => {
+                    let bin = SPEED_BINS
+                        .iter()
+                        .position(|bound| observation.avg_speed < *bound)
+                        .unwrap_or(SPEED_BINS.len());
+                    rose.counts[compass_point_index(direction)][bin] += 1;
+                }
+                None => rose.calm += 1,
+            }
+        }
+        rose
+    }
+
+    /// Percentage of all observations
+    pub fn percent(&self, count: u32) -> f32 {
+        percent(count, self.total)
+    }
+}
+
+/// Rideable and observed hours
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Hours {
+    pub rideable: u32,
+    pub observed: u32,
+}
+
+impl Hours {
+    /// Percentage of rideable hours. `None` if there are no observed hours
+    pub fn percent(&self) -> Option<f32> {
+        (self.observed > 0).then(|| percent(self.rideable, self.observed))
+    }
+}
+
+pub struct Statistics {
+    pub rose: WindRose,
+    /// Hours by month (0 is January)
+    pub months: [Hours; 12],
+    /// Hours by hour of the day
+    pub hours: [Hours; 24],
+    pub first_day: Option<NaiveDate>,
+    pub last_day: Option<NaiveDate>,
+}
+
+impl Statistics {
+    /// Calculates statistics of observations ordered by time. Rideable hours are determined by `rule`
+    pub fn new(observations: &[Observation], rule: &Rule) -> Self {
+        // (matching, total) observations of each local hour
+        let mut hours = BTreeMap::<(NaiveDate, u32), (u32, u32)>::new();
+        for observation in observations {
+            let key = (observation.time.date_naive(), observation.time.hour());
+            let (matching, total) = hours.entry(key).or_default();
+            *matching += rule.test(observation) as u32;
+            *total += 1;
+        }
+
+        let mut statistics = Self {
+            rose: WindRose::new(observations),
+            months: Default::default(),
+            hours: Default::default(),
+            first_day: hours.keys().next().map(|(date, _)| *date),
+            last_day: hours.keys().next_back().map(|(date, _)| *date),
+        };
+        for ((date, hour), (matching, total)) in hours {
+            let rideable = (2 * matching >= total) as u32;
+            for hours in [
+                &mut statistics.months[date.month0() as usize],
+                &mut statistics.hours[hour as usize],
+            ] {
+                hours.rideable += rideable;
+                hours.observed += 1;
+            }
+        }
+        statistics
+    }
+
+    /// Text report of a given spot
+    pub fn format(&self, spot: &str, units: SpeedUnit) -> String {
+        let (first_day, last_day) = match (self.first_day, self.last_day) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return format!("No observations of {} yet", spot),
+        };
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "Wind statistics of {} ({} – {}, {} observations)\n",
+            spot,
+            first_day.format("%d.%m.%Y"),
+            last_day.format("%d.%m.%Y"),
+            self.rose.total
+        );
+
+        let _ = writeln!(out, "Wind rose, % of observations ({}):", units);
+        let _ = write!(out, "{:<4}", "");
+        for label in speed_bin_labels(units) {
+            let _ = write!(out, "{:>6}", label);
+        }
+        out.push('\n');
+        for (idx, counts) in self.rose.counts.iter().enumerate() {
+            let _ = write!(out, "{:<4}", compass_point_name(idx as u16 * 45 / 2));
+            for count in counts {
+                let _ = write!(out, "{:>6.1}", self.rose.percent(*count));
+            }
+            out.push('\n');
+        }
+        let _ = writeln!(
+            out,
+            "Calm or variable: {:.1}%\n",
+            self.rose.percent(self.rose.calm)
+        );
+
+        let _ = writeln!(out, "Rideable hours by month:");
+        for (month, hours) in MONTHS.iter().zip(self.months) {
+            if let Some(percent) = hours.percent() {
+                let _ = writeln!(
+                    out,
+                    "{} {:>5.1}% ({} of {} h)",
+                    month, percent, hours.rideable, hours.observed
+                );
+            }
+        }
+
+        let _ = writeln!(out, "\nBest time of day:");
+        for (hour, hours) in self.hours.iter().enumerate() {
+            if let Some(percent) = hours.percent() {
+                let bar = "█".repeat((percent / 5.).round() as usize);
+                let _ = writeln!(out, "{:02}:00 {:>5.1}% {}", hour, percent, bar);
+            }
+        }
+        out
+    }
+
+    /// PNG images of wind rose, monthly climatology and time of day histogram
+    pub fn render(&self) -> Result<[Vec<u8>; 3]> {
+        let months = self.months.map(|h| h.percent().unwrap_or(0.));
+        let hours = self.hours.map(|h| h.percent().unwrap_or(0.));
+        Ok([
+            chart::wind_rose(&self.rose)?,
+            chart::bars(&months)?,
+            chart::bars(&hours)?,
+        ])
+    }
+}
+
+/// Labels of wind rose speed bins in given units (eg. `<7`, `7-11`, ..., `21+`)
+pub fn speed_bin_labels(units: SpeedUnit) -> Vec<String> {
+    let bound = |idx: usize| format!("{:.0}", units.from_ms(SPEED_BINS[idx]));
+    let last = SPEED_BINS.len() - 1;
+    let mut labels = vec![format!("<{}", bound(0))];
+    labels.extend((1..=last).map(|idx| format!("{}-{}", bound(idx - 1), bound(idx))));
+    labels.push(format!("{}+", bound(last)));
+    labels
+}
+
+fn percent(count: u32, total: u32) -> f32 {
+    if total == 0 {
+        0.
+    } else {
+        count as f32 * 100. / total as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::Sector;
+    use chrono::DateTime;
+
+    fn observation(time: &str, direction: Option<u16>, avg_speed: f32) -> Observation {
+        Observation {
+            time: DateTime::parse_from_rfc3339(time).unwrap(),
+            direction,
+            avg_speed,
+            gust_speed: None,
+        }
+    }
+
+    #[test]
+    fn wind_rose() {
+        let rose = WindRose::new(&[
+            observation("2022-10-29T12:00:00+10:00", Some(0), 2.),
+            observation("2022-10-29T12:10:00+10:00", Some(350), 6.),
+            observation("2022-10-29T12:20:00+10:00", Some(90), 12.),
+            observation("2022-10-29T12:30:00+10:00", None, 0.),
+        ]);
+        assert_eq!(4, rose.total);
+        assert_eq!(1, rose.calm);
+        assert_eq!([1, 0, 1, 0, 0], rose.counts[0]);
+        assert_eq!([0, 0, 0, 0, 1], rose.counts[4]);
+        assert_eq!(25., rose.percent(rose.calm));
+        assert_eq!(
+            vec!["<7", "7-11", "11-16", "16-21", "21+"],
+            speed_bin_labels(SpeedUnit::Knots)
+        );
+    }
+
+    #[test]
+    fn rideable_hours() {
+        let rule = Rule::speed_in_sector(5., Sector::NORTH_180);
+        let statistics = Statistics::new(
+            &[
+                // rideable: 2 of 3 observations match
+                observation("2022-10-29T12:00:00+10:00", Some(0), 6.),
+                observation("2022-10-29T12:20:00+10:00", Some(0), 7.),
+                observation("2022-10-29T12:40:00+10:00", Some(180), 7.),
+                // not rideable
+                observation("2022-10-29T13:00:00+10:00", Some(0), 2.),
+                observation("2022-11-01T12:00:00+10:00", None, 0.),
+            ],
+            &rule,
+        );
+        assert_eq!(
+            Hours {
+                rideable: 1,
+                observed: 2
+            },
+            statistics.months[9]
+        );
+        assert_eq!(Some(0.), statistics.months[10].percent());
+        assert_eq!(None, statistics.months[0].percent());
+        assert_eq!(Some(50.), statistics.hours[12].percent());
+        assert_eq!(Some(0.), statistics.hours[13].percent());
+
+        let report = statistics.format("rvs", SpeedUnit::MetersPerSecond);
+        assert!(report.contains("(29.10.2022 – 01.11.2022, 5 observations)"));
+        assert!(report.contains("Oct  50.0% (1 of 2 h)\nNov   0.0% (0 of 1 h)\n"));
+        assert!(report.contains("12:00  50.0% ██████████\n13:00   0.0% \n"));
+    }
+}
@@ -0,0 +1,159 @@
+//! Coordinated shutdown and supervised restart of long running tasks
+use crate::prelude::*;
+use std::{future::Future, time::Duration};
+use tokio::{
+    sync::watch,
+    time::{self, Instant},
+};
+
+/// Shutdown signal shared by all tasks
+///
+/// Tasks should check for shutdown only between units of work (eg. between observations), so work in progress
+/// (like sending notifications) is finished before exit.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+/// Triggers shutdown of all [`Shutdown`] clones
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+impl Shutdown {
+    pub fn new() -> (ShutdownTrigger, Shutdown) {
+        let (sender, receiver) = watch::channel(false);
+        (ShutdownTrigger(sender), Shutdown(receiver))
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Completes when shutdown is triggered
+    pub async fn wait(&mut self) {
+        while !self.is_triggered() {
+            if self.0.changed().await.is_err() {
+                // trigger is dropped, so shutdown will never happen
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+/// Exponential backoff delays between restarts
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            current: min,
+        }
+    }
+
+    /// Returns next delay doubling it for the following call
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.min;
+    }
+}
+
+/// Task running longer than this without failure is considered healthy, so backoff is reset
+const HEALTHY_RUN: Duration = Duration::from_secs(600);
+
+/// Runs a task created by `factory` restarting it with backoff if it fails or stops before shutdown
+pub async fn supervise<F, Fut>(
+    name: &str,
+    mut shutdown: Shutdown,
+    mut backoff: Backoff,
+    mut factory: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    loop {
+        let started = Instant::now();
+        match factory().await {
+            Ok(()) if shutdown.is_triggered() => return,
+            Ok(()) => warn!("Task '{}' stopped unexpectedly", name),
+            Err(e) => error!("Task '{}' failed: {:?}", name, e),
+        }
+        if shutdown.is_triggered() {
+            return;
+        }
+        if started.elapsed() >= HEALTHY_RUN {
+            backoff.reset();
+        }
+        let delay = backoff.next_delay();
+        info!("Restarting '{}' in {:?}", name, delay);
+        tokio::select! {
+            _ = time::sleep(delay) => {},
+            _ = shutdown.wait() => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use anyhow::bail;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn exponential_backoff() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        let delays = (0..5)
+            .map(|_| backoff.next_delay().as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![1, 2, 4, 5, 5], delays);
+        backoff.reset();
+        assert_eq!(Duration::from_secs(1), backoff.next_delay());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_task_is_restarted_until_shutdown() {
+        let (trigger, shutdown) = Shutdown::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+
+        let task = {
+            let runs = runs.clone();
+            supervise("test", shutdown.clone(), backoff, move || {
+                let runs = runs.clone();
+                let mut shutdown = shutdown.clone();
+                async move {
+                    if runs.fetch_add(1, Ordering::SeqCst) < 2 {
+                        bail!("failure");
+                    }
+                    shutdown.wait().await;
+                    Ok(())
+                }
+            })
+        };
+        let handle = tokio::spawn(task);
+
+        time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(3, runs.load(Ordering::SeqCst));
+
+        trigger.trigger();
+        handle.await.unwrap();
+        assert_eq!(3, runs.load(Ordering::SeqCst));
+    }
+}
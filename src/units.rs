@@ -0,0 +1,226 @@
+//! Wind speed units
+//!
+//! All speeds are stored and processed in m/s. Units are only used for parsing user input and displaying values.
+use crate::prelude::*;
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+const KNOT: f32 = 1852. / 3600.;
+const KMH: f32 = 1. / 3.6;
+const MPH: f32 = 0.44704;
+
+/// Lower bounds (m/s) of Beaufort scale forces 0-12
+const BEAUFORT: [f32; 13] = [
+    0., 0.3, 1.6, 3.4, 5.5, 8.0, 10.8, 13.9, 17.2, 20.8, 24.5, 28.5, 32.7,
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SpeedUnit {
+    #[default]
+    #[serde(rename = "m/s")]
+    MetersPerSecond,
+    #[serde(rename = "kn")]
+    Knots,
+    #[serde(rename = "km/h")]
+    KilometersPerHour,
+    #[serde(rename = "mph")]
+    MilesPerHour,
+    /// Beaufort scale. Fractional values are interpolated linearly between forces lower bounds
+    #[serde(rename = "Bft")]
+    Beaufort,
+}
+
+impl SpeedUnit {
+    /// Converts speed given in this unit to m/s
+    pub fn to_ms(self, value: f32) -> f32 {
+        use SpeedUnit::*;
+        match self {
+            MetersPerSecond => value,
+            Knots => value * KNOT,
+            KilometersPerHour => value * KMH,
+            MilesPerHour => value * MPH,
+            Beaufort => {
+                let value = value.clamp(0., (BEAUFORT.len() - 1) as f32);
+                let force = value.floor() as usize;
+                match BEAUFORT.get(force + 1) {
+                    Some(next) => {
+                        BEAUFORT[force] + (next - BEAUFORT[force]) * (value - force as f32)
+                    }
+                    None => BEAUFORT[force],
+                }
+            }
+        }
+    }
+
+    /// Converts speed given in m/s to this unit
+    pub fn from_ms(self, value: f32) -> f32 {
+        use SpeedUnit::*;
+        match self {
+            MetersPerSecond => value,
+            Knots => value / KNOT,
+            KilometersPerHour => value / KMH,
+            MilesPerHour => value / MPH,
+            Beaufort => {
+                let force = BEAUFORT.iter().rposition(|b| *b <= value).unwrap_or(0);
+                match BEAUFORT.get(force + 1) {
+                    Some(next) => {
+                        force as f32 + (value - BEAUFORT[force]) / (next - BEAUFORT[force])
+                    }
+                    None => force as f32,
+                }
+            }
+        }
+    }
+
+    /// Formats speed given in m/s using this unit (eg. `10.5 kn`)
+    pub fn format(self, value: f32) -> String {
+        match self {
+            SpeedUnit::Beaufort => format!("{} {}", self.from_ms(value).floor(), self),
+            _ => format!("{:.1} {}", self.from_ms(value), self),
+        }
+    }
+}
+
+impl Display for SpeedUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SpeedUnit::*;
+        let symbol = match self {
+            MetersPerSecond => "m/s",
+            Knots => "kn",
+            KilometersPerHour => "km/h",
+            MilesPerHour => "mph",
+            Beaufort => "Bft",
+        };
+        f.write_str(symbol)
+    }
+}
+
+impl FromStr for SpeedUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        use SpeedUnit::*;
+        let unit = match input.trim().to_lowercase().as_str() {
+            "m/s" | "ms" | "mps" | "м/с" => MetersPerSecond,
+            "kn" | "kt" | "kts" | "knot" | "knots" | "уз" => Knots,
+            "km/h" | "kmh" | "kph" | "км/ч" => KilometersPerHour,
+            "mph" | "mp/h" => MilesPerHour,
+            "bft" | "beaufort" => Beaufort,
+            _ => bail!(
+                "Unknown speed unit '{}'. Use m/s, kn, km/h, mph or Bft",
+                input
+            ),
+        };
+        Ok(unit)
+    }
+}
+
+/// Speed value with unit (eg. `15kn`, `7.5 m/s` or `5`). Unit is m/s if not given
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Speed {
+    pub value: f32,
+    pub unit: SpeedUnit,
+}
+
+impl Speed {
+    pub fn to_ms(self) -> f32 {
+        self.unit.to_ms(self.value)
+    }
+}
+
+impl FromStr for Speed {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let input = input.trim();
+        let split = input
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+            .unwrap_or(input.len());
+        let (value, unit) = input.split_at(split);
+        let value = value
+            .replace(',', ".")
+            .parse::<f32>()
+            .map_err(|_| anyhow!("Invalid speed '{}'", input))?;
+        let unit = match unit.trim() {
+            "" => SpeedUnit::MetersPerSecond,
+            unit => unit.parse()?,
+        };
+        Ok(Self { value, unit })
+    }
+}
+
+impl Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.unit {
+            SpeedUnit::MetersPerSecond => write!(f, "{}", self.value),
+            unit => write!(f, "{}{}", self.value, unit),
+        }
+    }
+}
+
+/// Parses speed with optional unit and returns it in m/s
+pub fn parse_speed(input: &str) -> Result<f32> {
+    Ok(input.parse::<Speed>()?.to_ms())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn assert_close(expected: f32, actual: f32) {
+        assert!(
+            (expected - actual).abs() < 0.01,
+            "{} != {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn conversions() {
+        assert_close(10., SpeedUnit::Knots.to_ms(19.438));
+        assert_close(19.438, SpeedUnit::Knots.from_ms(10.));
+        assert_close(36., SpeedUnit::KilometersPerHour.from_ms(10.));
+        assert_close(22.369, SpeedUnit::MilesPerHour.from_ms(10.));
+        assert_close(0.3, SpeedUnit::Beaufort.to_ms(1.));
+        assert_close(8., SpeedUnit::Beaufort.to_ms(5.));
+        assert_close(5., SpeedUnit::Beaufort.from_ms(8.));
+        assert_close(5.5, SpeedUnit::Beaufort.from_ms(9.4));
+        assert_close(12., SpeedUnit::Beaufort.from_ms(40.));
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!("5.0 m/s", SpeedUnit::MetersPerSecond.format(5.));
+        assert_eq!("9.7 kn", SpeedUnit::Knots.format(5.));
+        assert_eq!("18.0 km/h", SpeedUnit::KilometersPerHour.format(5.));
+        assert_eq!("3 Bft", SpeedUnit::Beaufort.format(5.));
+    }
+
+    #[test]
+    fn parsing() -> Result<()> {
+        assert_close(15. * KNOT, parse_speed("15kn")?);
+        assert_close(15. * KNOT, parse_speed("15 knots")?);
+        assert_close(7.5, parse_speed("7,5 м/с")?);
+        assert_close(7., parse_speed("7")?);
+        assert_close(10., parse_speed("36km/h")?);
+        assert_close(10.8, parse_speed("6bft")?);
+
+        assert!(parse_speed("fast").is_err());
+        assert!(parse_speed("15 furlongs").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn speed_display_roundtrip() -> Result<()> {
+        for input in ["15kn", "7.5", "20km/h", "5Bft"] {
+            assert_eq!(input, input.parse::<Speed>()?.to_string());
+        }
+        Ok(())
+    }
+}
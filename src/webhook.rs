@@ -0,0 +1,68 @@
+//! Telegram webhook mode
+//!
+//! Instead of long polling, Telegram POSTs updates to a public URL (usually a reverse proxy forwarding to the
+//! listen address). Telegram passes the secret token in `X-Telegram-Bot-Api-Secret-Token` header, so requests
+//! not coming from Telegram are rejected. The bot refuses to register a webhook without secret token.
+use crate::prelude::*;
+use anyhow::bail;
+use axum::Router;
+use reqwest::Url;
+use std::{convert::Infallible, future::Future, net::SocketAddr};
+use teloxide::{
+    dispatching::update_listeners::{
+        webhooks::{self, Options},
+        UpdateListener,
+    },
+    Bot,
+};
+
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    /// Address webhook HTTP server is listening on
+    pub listen: SocketAddr,
+    /// Public URL Telegram is sending updates to. Its path is used as the route of the webhook
+    pub url: Url,
+    /// Secret token Telegram sends with every update (1-256 characters `A-Z`, `a-z`, `0-9`, `_` and `-`)
+    pub secret: Option<String>,
+}
+
+impl Webhook {
+    fn options(&self) -> Options {
+        let options = Options::new(self.listen, self.url.clone());
+        match &self.secret {
+            Some(secret) => options.secret_token(secret.clone()),
+            None => options,
+        }
+    }
+
+    /// Registers webhook with Telegram. Fails if there is no secret token
+    ///
+    /// Returns update listener, a future completing when the listener is stopped (webhook is deleted on completion)
+    /// and a router receiving updates which should be served on the listen address.
+    pub async fn setup(
+        &self,
+        bot: Bot,
+    ) -> Result<(
+        impl UpdateListener<Err = Infallible>,
+        impl Future<Output = ()> + Send,
+        Router,
+    )> {
+        if self.secret.is_none() {
+            bail!("Webhook without secret token would accept forged updates");
+        }
+        Ok(webhooks::axum_to_router(bot, self.options()).await?)
+    }
+
+    /// Same as [`Webhook::setup()`], but doesn't register webhook with Telegram
+    ///
+    /// Useful for local testing with simulated update POSTs.
+    pub fn local(
+        &self,
+    ) -> (
+        impl UpdateListener<Err = Infallible>,
+        impl Future<Output = ()>,
+        Router,
+    ) {
+        webhooks::axum_no_setup(self.options())
+    }
+}
@@ -0,0 +1,312 @@
+use chrono::{DateTime, Duration, Utc};
+use diesel::{Connection, SqliteConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::TcpListener,
+    sync::{Arc, Mutex},
+};
+use telewind::{
+    api::Api,
+    fetch::{self, Fetcher},
+    health::Health,
+    history::History,
+    http,
+    live::{LiveEvent, LiveFeed},
+    metrics,
+    parser::Observation,
+    prelude::*,
+    Subscriptions, WindState,
+};
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
+
+#[tokio::test]
+async fn metrics_endpoint() -> Result<()> {
+    metrics::FETCH_ATTEMPTS.inc(&["http-test"]);
+
+    let (url, _, _) = spawn_server()?;
+    let response = reqwest::get(format!("{url}/metrics")).await?;
+    assert!(response.status().is_success());
+
+    let body = response.text().await?;
+    assert!(body.contains("# TYPE telewind_fetch_attempts_total counter\n"));
+    assert!(body.contains("telewind_fetch_attempts_total{source=\"http-test\"} 1\n"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn health_endpoints() -> Result<()> {
+    let (url, health, _) = spawn_server()?;
+
+    let response = reqwest::get(format!("{url}/healthz")).await?;
+    assert_eq!(503, response.status().as_u16());
+    let expected = json!({"database": true, "dispatcher": false, "observations": false});
+    assert_eq!(expected, response.json::<Value>().await?);
+
+    health.set_dispatcher_running(true);
+    let response = reqwest::get(format!("{url}/healthz")).await?;
+    assert_eq!(200, response.status().as_u16());
+    let response = reqwest::get(format!("{url}/readyz")).await?;
+    assert_eq!(503, response.status().as_u16());
+
+    health.observed(&(Utc::now() - Duration::minutes(31)));
+    let response = reqwest::get(format!("{url}/readyz")).await?;
+    assert_eq!(503, response.status().as_u16());
+    health.observed(&Utc::now());
+    let response = reqwest::get(format!("{url}/readyz")).await?;
+    assert_eq!(200, response.status().as_u16());
+
+    health.set_database_available(false);
+    let response = reqwest::get(format!("{url}/healthz")).await?;
+    assert_eq!(503, response.status().as_u16());
+    Ok(())
+}
+
+#[tokio::test]
+async fn api_requires_token() -> Result<()> {
+    let (url, _, _) = spawn_server()?;
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{url}/api/spots")).send().await?;
+    assert_eq!(401, response.status().as_u16());
+    let response = client
+        .get(format!("{url}/api/spots"))
+        .bearer_auth("wrong")
+        .send()
+        .await?;
+    assert_eq!(401, response.status().as_u16());
+    let response = client
+        .get(format!("{url}/api/spots"))
+        .bearer_auth("secreT")
+        .send()
+        .await?;
+    assert_eq!(401, response.status().as_u16());
+    Ok(())
+}
+
+#[tokio::test]
+async fn api_observations_and_trackers() -> Result<()> {
+    let (url, _, api) = spawn_server()?;
+    let client = reqwest::Client::new();
+    let get = |path: &str| {
+        client
+            .get(format!("{url}/api{path}"))
+            .bearer_auth(TOKEN)
+            .send()
+    };
+
+    let response = get("/spots/rvs/observations/latest").await?;
+    assert_eq!(404, response.status().as_u16());
+
+    let time = |s| DateTime::parse_from_rfc3339(s).unwrap();
+    for (t, avg_speed) in [
+        ("2022-10-29T22:45:00+10:00", 3.5),
+        ("2022-10-29T22:46:00+10:00", 4.),
+    ] {
+        let observation = Observation {
+            time: time(t),
+            direction: Some(315),
+            avg_speed,
+            gust_speed: None,
+        };
+        api.history.lock().unwrap().save("rvs", &observation)?;
+    }
+
+    assert_eq!(json!(["rvs"]), get("/spots").await?.json::<Value>().await?);
+
+    let latest = get("/spots/rvs/observations/latest")
+        .await?
+        .json::<Value>()
+        .await?;
+    assert_eq!(json!(4.0), latest["avg_speed"]);
+    assert_eq!(json!("2022-10-29T22:46:00+10:00"), latest["time"]);
+
+    let path =
+        "/spots/rvs/observations?from=2022-10-29T22:00:00%2B10:00&to=2022-10-29T22:46:00%2B10:00";
+    let observations = get(path).await?.json::<Value>().await?;
+    assert_eq!(1, observations.as_array().unwrap().len());
+
+    api.trackers
+        .lock()
+        .unwrap()
+        .insert(1, WindState::Candidate(2));
+    assert_eq!(
+        json!([{"user_id": 1, "state": "candidate", "steps": 2}]),
+        get("/trackers").await?.json::<Value>().await?
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn api_subscriptions() -> Result<()> {
+    let (url, _, _) = spawn_server()?;
+    let client = reqwest::Client::new();
+    let url = |path: &str| format!("{url}/api{path}");
+
+    let response = client
+        .get(url("/subscriptions/1"))
+        .bearer_auth(TOKEN)
+        .send()
+        .await?;
+    assert_eq!(404, response.status().as_u16());
+
+    let response = client
+        .put(url("/subscriptions/1"))
+        .bearer_auth(TOKEN)
+        .send()
+        .await?;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(json!(1), response.json::<Value>().await?["user_id"]);
+
+    let update =
+        json!({"rule": "avg >= 7 and dir in NE..E", "speed_unit": "kn", "digest_at": "21:30"});
+    let response = client
+        .patch(url("/subscriptions/1"))
+        .bearer_auth(TOKEN)
+        .json(&update)
+        .send()
+        .await?;
+    let subscription = response.json::<Value>().await?;
+    assert_eq!(
+        json!("avg >= 7 and direction in NE..E"),
+        subscription["rule"]
+    );
+    assert_eq!(json!("kn"), subscription["speed_unit"]);
+    assert_eq!(json!("21:30"), subscription["digest_at"]);
+
+    // null resets a setting, missing settings are left intact
+    let update = json!({"rule": null});
+    let response = client
+        .patch(url("/subscriptions/1"))
+        .bearer_auth(TOKEN)
+        .json(&update)
+        .send()
+        .await?;
+    let subscription = response.json::<Value>().await?;
+    assert_eq!(Value::Null, subscription["rule"]);
+    assert_eq!(json!("21:30"), subscription["digest_at"]);
+
+    let update = json!({"rule": "avg >=", "digest_at": "07:00"});
+    let response = client
+        .patch(url("/subscriptions/1"))
+        .bearer_auth(TOKEN)
+        .json(&update)
+        .send()
+        .await?;
+    assert_eq!(400, response.status().as_u16());
+
+    let response = client
+        .get(url("/subscriptions"))
+        .bearer_auth(TOKEN)
+        .send()
+        .await?;
+    let subscriptions = response.json::<Value>().await?;
+    assert_eq!(json!("21:30"), subscriptions[0]["digest_at"]);
+
+    let response = client
+        .delete(url("/subscriptions/1"))
+        .bearer_auth(TOKEN)
+        .send()
+        .await?;
+    assert_eq!(204, response.status().as_u16());
+    let response = client
+        .delete(url("/subscriptions/1"))
+        .bearer_auth(TOKEN)
+        .send()
+        .await?;
+    assert_eq!(404, response.status().as_u16());
+    Ok(())
+}
+
+#[tokio::test]
+async fn live_feed() -> Result<()> {
+    let feed = Arc::new(LiveFeed::default());
+    let (url, _, _) = spawn_server_with_feed(feed.clone())?;
+
+    let mut response = reqwest::get(format!("{url}/live")).await?;
+    assert_eq!(
+        Some("text/event-stream"),
+        response
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+    );
+
+    feed.publish(LiveEvent::WindState {
+        spot: "rvs".to_string(),
+        from: WindState::Candidate(5),
+        to: WindState::High,
+    });
+    let chunk = response.chunk().await?.unwrap();
+    assert_eq!(
+        "event:wind_state\n\
+        data:{\"type\":\"wind_state\",\"spot\":\"rvs\",\"from\":{\"state\":\"candidate\",\"steps\":5},\"to\":{\"state\":\"high\"}}\n\n",
+        std::str::from_utf8(&chunk)?
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn conditional_fetch() -> Result<()> {
+    use axum::{
+        http::{header, HeaderMap, StatusCode},
+        routing::get,
+        Router,
+    };
+
+    async fn page(headers: HeaderMap) -> (StatusCode, HeaderMap, &'static str) {
+        let mut response = HeaderMap::new();
+        response.insert(header::ETAG, "\"v1\"".parse().unwrap());
+        match headers.get(header::IF_NONE_MATCH) {
+            Some(etag) if etag == "\"v1\"" => (StatusCode::NOT_MODIFIED, response, ""),
+            _ => (StatusCode::OK, response, "page"),
+        }
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/wind", listener.local_addr()?);
+    let router = Router::new().route("/wind", get(page));
+    tokio::spawn(axum::Server::from_tcp(listener)?.serve(router.into_make_service()));
+
+    let client = fetch::http_client(std::time::Duration::from_secs(5), fetch::USER_AGENT, None)?;
+    let mut fetcher = Fetcher::new(&url, &client);
+    assert_eq!(vec!["page"], fetcher.fetch().await?);
+    assert!(fetcher.fetch().await?.is_empty());
+    Ok(())
+}
+
+const TOKEN: &str = "secret";
+
+/// Starts server on a random port and returns its base url
+fn spawn_server() -> Result<(String, Arc<Health>, Arc<Api>)> {
+    spawn_server_with_feed(Arc::new(LiveFeed::default()))
+}
+
+fn spawn_server_with_feed(feed: Arc<LiveFeed>) -> Result<(String, Arc<Health>, Arc<Api>)> {
+    let history = Arc::new(Mutex::new(History::with_connection(init_connection()?)?));
+    let subscriptions = Subscriptions::with_connection(init_connection()?)?;
+    let health = Arc::new(Health::new(Duration::minutes(30)));
+    let api = Arc::new(Api {
+        token: TOKEN.to_string(),
+        subscriptions: Arc::new(Mutex::new(subscriptions)),
+        history,
+        trackers: Arc::new(Mutex::new(HashMap::new())),
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let router = http::router(health.clone(), feed.clone(), Some(api.clone()));
+    let server = axum::Server::from_tcp(listener)?.serve(router.into_make_service());
+    tokio::spawn(server);
+    Ok((url, health, api))
+}
+
+fn init_connection() -> Result<SqliteConnection> {
+    let mut connection = SqliteConnection::establish(":memory:")?;
+    connection
+        .run_pending_migrations(MIGRATIONS)
+        .expect("Unable to run migrations");
+    Ok(connection)
+}
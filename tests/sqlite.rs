@@ -1,6 +1,10 @@
+use chrono::{DateTime, Duration, NaiveTime};
 use diesel::{Connection, SqliteConnection};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use telewind::{prelude::*, Subscriptions};
+use telewind::{
+    checkin::CheckIn, history::History, parser::Observation, prelude::*, rule::Rule,
+    session::Session, units::SpeedUnit, Subscriptions,
+};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 
@@ -17,6 +21,7 @@ fn saving_subscriptions() -> Result<()> {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn removing_subscriptions() -> Result<()> {
     let mut subscriptions = init_subscriptions()?;
 
@@ -28,10 +33,258 @@ fn removing_subscriptions() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn saving_rules() -> Result<()> {
+    let mut subscriptions = init_subscriptions()?;
+
+    let rule: Rule = "avg >= 7 and direction in 45..90".parse()?;
+    assert!(!subscriptions.set_rule(1, Some(&rule))?);
+
+    subscriptions.new_subscription(1)?;
+    assert!(subscriptions.set_rule(1, Some(&rule))?);
+    let result = subscriptions.list_subscriptions()?;
+    assert_eq!(Some(rule.to_string()), result[0].rule);
+
+    subscriptions.set_rule(1, None)?;
+    let result = subscriptions.list_subscriptions()?;
+    assert_eq!(None, result[0].rule);
+
+    Ok(())
+}
+
+#[test]
+fn saving_threshold_and_units() -> Result<()> {
+    let mut subscriptions = init_subscriptions()?;
+
+    subscriptions.new_subscription(1)?;
+    let subscription = subscriptions.find_subscription(1)?.unwrap();
+    assert_eq!(None, subscription.threshold);
+    assert_eq!(SpeedUnit::MetersPerSecond, subscription.speed_unit());
+
+    assert!(subscriptions.set_threshold(1, Some(7.5))?);
+    assert!(subscriptions.set_speed_unit(1, SpeedUnit::Knots)?);
+    let subscription = subscriptions.find_subscription(1)?.unwrap();
+    assert_eq!(Some(7.5), subscription.threshold);
+    assert_eq!(SpeedUnit::Knots, subscription.speed_unit());
+
+    assert!(!subscriptions.set_threshold(2, Some(7.5))?);
+    assert!(subscriptions.find_subscription(2)?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn saving_digest_time() -> Result<()> {
+    let mut subscriptions = init_subscriptions()?;
+
+    subscriptions.new_subscription(1)?;
+    let time = NaiveTime::from_hms(21, 30, 0);
+    assert!(subscriptions.set_digest_time(1, Some(time))?);
+    let subscription = subscriptions.find_subscription(1)?.unwrap();
+    assert_eq!(Some(time), subscription.digest_at());
+
+    subscriptions.set_digest_time(1, None)?;
+    let subscription = subscriptions.find_subscription(1)?.unwrap();
+    assert_eq!(None, subscription.digest_at());
+
+    Ok(())
+}
+
+#[test]
+fn saving_observations() -> Result<()> {
+    let mut history = History::with_connection(init_connection()?)?;
+
+    let time = |s| DateTime::parse_from_rfc3339(s).unwrap();
+    let observation = |t, avg_speed| Observation {
+        time: time(t),
+        direction: Some(315),
+        avg_speed,
+        gust_speed: Some(avg_speed * 1.5),
+    };
+
+    assert!(history.save("rvs", &observation("2022-10-29T22:46:00+10:00", 2.5))?);
+    assert!(history.save("rvs", &observation("2022-10-29T22:45:00+10:00", 3.5))?);
+    assert!(history.save("other", &observation("2022-10-29T22:45:00+10:00", 4.5))?);
+    // duplicate
+    assert!(!history.save("rvs", &observation("2022-10-29T22:46:00+10:00", 2.5))?);
+
+    let from = time("2022-10-29T22:00:00+10:00");
+    let to = time("2022-10-29T23:00:00+10:00");
+    let result = history.list("rvs", &from, &to)?;
+    assert_eq!(2, result.len());
+    assert_eq!(time("2022-10-29T22:45:00+10:00"), result[0].time);
+    assert_eq!("+10:00", result[0].time.offset().to_string());
+    assert_eq!(3.5, result[0].avg_speed);
+    assert_eq!(Some(3.75), result[1].gust_speed);
+
+    let result = history.list("rvs", &from, &time("2022-10-29T22:46:00+10:00"))?;
+    assert_eq!(1, result.len());
+
+    // calm
+    let calm = Observation {
+        direction: None,
+        ..observation("2022-10-29T22:47:00+10:00", 0.)
+    };
+    assert!(history.save("rvs", &calm)?);
+    let result = history.list("rvs", &from, &to)?;
+    assert_eq!(None, result[2].direction);
+
+    Ok(())
+}
+
+#[test]
+fn importing_observations() -> Result<()> {
+    let mut history = History::with_connection(init_connection()?)?;
+
+    let observation = |t| Observation {
+        time: DateTime::parse_from_rfc3339(t).unwrap(),
+        direction: None,
+        avg_speed: 0.,
+        gust_speed: None,
+    };
+    let batch = [
+        observation("2022-10-29T22:45:00+10:00"),
+        observation("2022-10-29T22:46:00+10:00"),
+    ];
+    assert_eq!(2, history.save_all("rvs", &batch)?);
+    // overlapping snapshot
+    let batch = [
+        observation("2022-10-29T22:46:00+10:00"),
+        observation("2022-10-29T22:47:00+10:00"),
+    ];
+    assert_eq!(1, history.save_all("rvs", &batch)?);
+    assert_eq!(1, history.save_all("other", &batch[..1])?);
+
+    let from = batch[0].time - chrono::Duration::hours(1);
+    let to = batch[1].time + chrono::Duration::hours(1);
+    assert_eq!(3, history.list("rvs", &from, &to)?.len());
+    Ok(())
+}
+
+#[test]
+fn saving_sessions() -> Result<()> {
+    let mut history = History::with_connection(init_connection()?)?;
+
+    let time = |s| DateTime::parse_from_rfc3339(s).unwrap();
+    let mut session = Session {
+        start: time("2022-10-29T12:00:00+10:00"),
+        end: time("2022-10-29T12:00:00+10:00"),
+        peak_speed: 6.,
+        avg_speed: 6.,
+        direction: None,
+        observations: 1,
+    };
+    let id = history.save_session("rvs", &session)?;
+    history.save_session("other", &session)?;
+
+    session.end = time("2022-10-29T14:30:00+10:00");
+    session.peak_speed = 9.5;
+    session.direction = Some(315);
+    session.observations = 16;
+    history.update_session(id, "rvs", &session)?;
+
+    let from = time("2022-10-29T00:00:00+10:00");
+    let to = time("2022-10-30T00:00:00+10:00");
+    assert_eq!(vec![session], history.list_sessions("rvs", &from, &to)?);
+    assert!(history
+        .list_sessions("rvs", &to, &(to + chrono::Duration::days(1)))?
+        .is_empty());
+    Ok(())
+}
+
+#[test]
+fn saving_check_ins() -> Result<()> {
+    let mut history = History::with_connection(init_connection()?)?;
+
+    let time = DateTime::parse_from_rfc3339("2022-10-29T12:00:00+10:00").unwrap();
+    let session = Session {
+        start: time,
+        end: time,
+        peak_speed: 6.,
+        avg_speed: 6.,
+        direction: None,
+        observations: 1,
+    };
+    let id = history.save_session("rvs", &session)?;
+    assert!(history.check_ins(id)?.format().is_empty());
+
+    history.check_in(id, 1, "Alex", CheckIn::Going)?;
+    history.check_in(id, 2, "Maria", CheckIn::Going)?;
+    history.check_in(id, 1, "Alex", CheckIn::NotToday)?;
+    let check_ins = history.check_ins(id)?;
+    assert_eq!(&["Maria".to_string()], check_ins.names(CheckIn::Going));
+    assert_eq!(1, check_ins.count(CheckIn::NotToday));
+
+    history.save_alert(id, 10, 100, "Wind is growing up")?;
+    history.save_alert(id, 20, 200, "Wind is growing up")?;
+    let alerts = history.alerts(id)?;
+    assert_eq!(2, alerts.len());
+    assert_eq!((20, 200), (alerts[1].chat_id, alerts[1].message_id));
+    assert!(history.alerts(id + 1)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn saving_forecasts() -> Result<()> {
+    let mut history = History::with_connection(init_connection()?)?;
+
+    let time = |s| DateTime::parse_from_rfc3339(s).unwrap();
+    let hour = |t, avg_speed| Observation {
+        time: time(t),
+        direction: Some(0),
+        avg_speed,
+        gust_speed: None,
+    };
+
+    let fetched_at = time("2022-12-14T10:30:00+10:00");
+    let forecast = [
+        hour("2022-12-14T10:00:00+10:00", 5.),
+        hour("2022-12-14T11:00:00+10:00", 6.),
+        hour("2022-12-14T12:00:00+10:00", 7.),
+    ];
+    // past hours are not saved
+    assert_eq!(2, history.save_forecast("rvs", &fetched_at, &forecast)?);
+
+    // newer forecast is listed instead of previous one
+    let fetched_at = time("2022-12-14T10:45:00+10:00");
+    let forecast = [hour("2022-12-14T11:00:00+10:00", 8.)];
+    assert_eq!(1, history.save_forecast("rvs", &fetched_at, &forecast)?);
+
+    let from = time("2022-12-14T00:00:00+10:00");
+    let to = time("2022-12-15T00:00:00+10:00");
+    let result = history.list_forecast("rvs", &from, &to)?;
+    assert_eq!(2, result.len());
+    assert_eq!(8., result[0].avg_speed);
+    assert_eq!(7., result[1].avg_speed);
+
+    // previous forecast is still listed as forecasted in advance
+    let result = history.list_forecast_ahead("rvs", &from, &to, Duration::minutes(20))?;
+    assert_eq!(2, result.len());
+    assert_eq!(6., result[0].avg_speed);
+    assert_eq!(7., result[1].avg_speed);
+    let result = history.list_forecast_ahead("rvs", &from, &to, Duration::minutes(60))?;
+    assert_eq!(1, result.len());
+    assert_eq!(7., result[0].avg_speed);
+
+    let mut subscriptions = init_subscriptions()?;
+    subscriptions.new_subscription(1)?;
+    let at = NaiveTime::from_hms(20, 0, 0);
+    assert!(subscriptions.set_forecast_time(1, Some(at))?);
+    let subscription = subscriptions.find_subscription(1)?.unwrap();
+    assert_eq!(Some(at), subscription.forecast_at());
+
+    Ok(())
+}
+
+#[allow(clippy::needless_question_mark)]
 fn init_subscriptions() -> Result<Subscriptions> {
+    Ok(Subscriptions::with_connection(init_connection()?)?)
+}
+
+fn init_connection() -> Result<SqliteConnection> {
     let mut connection = SqliteConnection::establish(":memory:")?;
     connection
         .run_pending_migrations(MIGRATIONS)
         .expect("Unable to run migrations");
-    Ok(Subscriptions::with_connection(connection)?)
+    Ok(connection)
 }
@@ -0,0 +1,81 @@
+use futures::StreamExt;
+use serde_json::json;
+use std::net::TcpListener;
+use telewind::{prelude::*, webhook::Webhook};
+use teloxide::Bot;
+use teloxide::{
+    dispatching::update_listeners::AsUpdateStream,
+    types::{MediaKind, MessageKind, UpdateKind},
+};
+
+const SECRET_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+#[tokio::test]
+async fn simulated_update_post() -> Result<()> {
+    let webhook = Webhook {
+        listen: "127.0.0.1:0".parse()?,
+        url: "https://example.com/telegram".parse()?,
+        secret: Some("secret".to_string()),
+    };
+    let (mut listener, _stop, router) = webhook.local();
+
+    let tcp = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/telegram", tcp.local_addr()?);
+    tokio::spawn(axum::Server::from_tcp(tcp)?.serve(router.into_make_service()));
+
+    let update = json!({
+        "update_id": 1,
+        "message": {
+            "message_id": 1,
+            "date": 1670000000,
+            "chat": {"id": 42, "type": "private", "first_name": "Test"},
+            "from": {"id": 42, "is_bot": false, "first_name": "Test"},
+            "text": "/subscribe"
+        }
+    });
+    let client = reqwest::Client::new();
+
+    let response = client.post(&url).json(&update).send().await?;
+    assert_eq!(401, response.status().as_u16());
+    let response = client
+        .post(&url)
+        .header(SECRET_HEADER, "wrong")
+        .json(&update)
+        .send()
+        .await?;
+    assert_eq!(401, response.status().as_u16());
+
+    let response = client
+        .post(&url)
+        .header(SECRET_HEADER, "secret")
+        .json(&update)
+        .send()
+        .await?;
+    assert_eq!(200, response.status().as_u16());
+
+    let mut updates = Box::pin(listener.as_stream());
+    let update = updates.next().await.unwrap().unwrap();
+    let text = match update.kind {
+        UpdateKind::Message(message) => match message.kind {
+            MessageKind::Common(common) => match common.media_kind {
+                MediaKind::Text(text) => Some(text.text),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    };
+    assert_eq!(Some("/subscribe".to_string()), text);
+    Ok(())
+}
+
+#[tokio::test]
+async fn secret_is_required() -> Result<()> {
+    let webhook = Webhook {
+        listen: "127.0.0.1:0".parse()?,
+        url: "https://example.com/telegram".parse()?,
+        secret: None,
+    };
+    assert!(webhook.setup(Bot::new("token")).await.is_err());
+    Ok(())
+}